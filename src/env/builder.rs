@@ -0,0 +1,488 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` environments builder
+use crate::{
+    env::{
+        environments::{self, default_path, read_path},
+        value,
+        var::process_vars,
+        Environments, StdVarReader,
+    },
+    error::Result,
+    hierarchy::Hierarchy,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    fmt,
+    io::Read,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+/// Where the builder reads the TOML from
+enum Source<'a> {
+    /// A path to a TOML file
+    Path(PathBuf),
+    /// A reader supplying TOML
+    Reader(Box<dyn Read + 'a>),
+}
+
+impl fmt::Debug for Source<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => f.debug_tuple("Path").field(path).finish(),
+            Self::Reader(_) => f.debug_tuple("Reader").finish(),
+        }
+    }
+}
+
+/// Build an `Environments` from a combination of loading options.
+///
+/// By default, the TOML is read from the `[envs]` table in the file named by
+/// the `TOMLENV_PATH` environment variable, or `env.toml` if it is unset, the
+/// `[defaults]` table is applied, unknown fields are ignored, no environment
+/// variable overrides are applied, and the current environment is selected
+/// with the `env` variable.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::{Environment, Environments, EnvironmentsBuilder, Result};
+/// # use serde::{Deserialize, Serialize};
+/// # use std::env;
+/// # use std::io::Cursor;
+/// #
+/// # fn foo() -> Result<()> {
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct RuntimeEnv {
+///     name: String,
+/// }
+///
+/// let toml = r#"[envs.prod]
+/// name = "Production"
+///
+/// [envs.dev]
+/// name = "Development"
+/// "#;
+///
+/// let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+///     .reader(Cursor::new(toml))
+///     .var_name("MY_APP_ENV")
+///     .build()?;
+///
+/// env::set_var("MY_APP_ENV", "dev");
+/// assert_eq!(envs.current()?.name, "Development");
+/// #   Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct EnvironmentsBuilder<'a, S, T> {
+    /// Where to read the TOML from
+    source: Option<Source<'a>>,
+    /// The variable used to select the current environment
+    var: Option<String>,
+    /// The top-level table holding the environments
+    table_name: Option<String>,
+    /// Expand environment variable references in the string values?
+    expand_env: bool,
+    /// Apply the `[defaults]` table?
+    defaults: bool,
+    /// Reject fields that are not part of the config type?
+    deny_unknown_fields: bool,
+    /// The prefix for environment variable overrides
+    env_overrides: Option<String>,
+    /// The environment hierarchy and config types
+    phantom: PhantomData<(S, T)>,
+}
+
+impl<S, T> Default for EnvironmentsBuilder<'_, S, T> {
+    fn default() -> Self {
+        Self {
+            source: None,
+            var: None,
+            table_name: None,
+            expand_env: false,
+            defaults: true,
+            deny_unknown_fields: false,
+            env_overrides: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'a, S, T> EnvironmentsBuilder<'a, S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Create a new builder with the default options.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the variable used to select the current environment.
+    #[must_use]
+    pub fn var_name<U>(mut self, var: U) -> Self
+    where
+        U: Into<String>,
+    {
+        self.var = Some(var.into());
+        self
+    }
+
+    /// Read the environments from the given top-level table, e.g.
+    /// `[environments.prod]`, rather than `[envs.prod]`.
+    #[must_use]
+    pub fn table_name<U>(mut self, table_name: U) -> Self
+    where
+        U: Into<String>,
+    {
+        self.table_name = Some(table_name.into());
+        self
+    }
+
+    /// Read the TOML from the given path.
+    #[must_use]
+    pub fn path<P>(mut self, path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.source = Some(Source::Path(path.as_ref().to_path_buf()));
+        self
+    }
+
+    /// Read the TOML from the given reader.
+    #[must_use]
+    pub fn reader<R>(mut self, reader: R) -> Self
+    where
+        R: Read + 'a,
+    {
+        self.source = Some(Source::Reader(Box::new(reader)));
+        self
+    }
+
+    /// Substitute `${NAME}` and `$NAME` references in the string values of the
+    /// TOML with the values of those environment variables.  A reference to an
+    /// unset variable is an error.  Use `$$` for a literal `$`.
+    ///
+    /// References are expanded in the parsed string values, not in the raw
+    /// text before parsing, so a variable containing quotes or newlines can't
+    /// change the structure of the TOML.  References in keys, comments or
+    /// non-string values are left as they are.
+    #[must_use]
+    pub fn expand_env(mut self, expand_env: bool) -> Self {
+        self.expand_env = expand_env;
+        self
+    }
+
+    /// Apply the top-level `[defaults]` table to every environment.
+    #[must_use]
+    pub fn with_defaults(mut self, defaults: bool) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Reject any fields in the environment tables that are not part of the
+    /// config type, e.g. misspelled keys, which serde otherwise ignores.
+    /// Fields added by `env_overrides` are checked as well.
+    #[must_use]
+    pub fn deny_unknown_fields(mut self, deny_unknown_fields: bool) -> Self {
+        self.deny_unknown_fields = deny_unknown_fields;
+        self
+    }
+
+    /// Apply environment variable overrides with the given prefix.  See
+    /// `Environments::with_env_overrides`.
+    #[must_use]
+    pub fn env_overrides<U>(mut self, prefix: U) -> Self
+    where
+        U: Into<String>,
+    {
+        self.env_overrides = Some(prefix.into());
+        self
+    }
+
+    /// Build the environments.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn build(self) -> Result<Environments<S, T>> {
+        let buffer = match self.source {
+            Some(Source::Reader(mut reader)) => {
+                let mut buffer = String::new();
+                let _ = reader.read_to_string(&mut buffer)?;
+                buffer
+            }
+            Some(Source::Path(path)) => read_path(&path)?,
+            None => read_path(&default_path())?,
+        };
+
+        let mut document: toml::Value = toml::from_str(&buffer)?;
+        if self.expand_env {
+            value::expand_vars(&mut document, &StdVarReader)?;
+        }
+
+        let table_name = self.table_name.as_deref().unwrap_or("envs");
+        value::rename_table(&mut document, table_name, "envs");
+        let mut declared = if self.deny_unknown_fields {
+            Some(document.clone())
+        } else {
+            None
+        };
+        let mut document = environments::document(document, "envs", self.defaults)?;
+        if let Some(prefix) = &self.env_overrides {
+            let vars: Vec<(String, String)> = process_vars().collect();
+            value::apply_env_overrides(&mut document, prefix, vars.iter().cloned())?;
+            // Check the fields an override adds too, e.g. a misspelled one.
+            if let Some(declared) = &mut declared {
+                value::apply_env_overrides(declared, prefix, vars)?;
+            }
+        }
+        let envs: Environments<S, T> = match declared {
+            Some(declared) => Environments::parse_strict(document, &declared)?,
+            None => document.try_into()?,
+        };
+        let mut envs = envs.in_declared_order(&mut toml::Deserializer::new(&buffer), table_name);
+        if let Some(var) = self.var {
+            envs.set_var_name(var);
+        }
+        Ok(envs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EnvironmentsBuilder;
+    use crate::env::{Environment, Environments};
+    use serde::{Deserialize, Serialize};
+    use std::{env, io::Cursor};
+
+    const TOML: &str = r#"[defaults]
+key = "default-key"
+
+[envs.prod]
+name = "Production"
+key = "abcd-123-efg-45"
+
+[envs.dev]
+name = "Development"
+"#;
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct RuntimeEnv {
+        name: String,
+        key: Option<String>,
+    }
+
+    #[test]
+    fn build() {
+        env::set_var("TOMLENV_BUILDER", "dev");
+        env::set_var("TOMLENV_BUILDER_OVERRIDE_PROD_NAME", "Overridden");
+        let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(TOML))
+            .var_name("TOMLENV_BUILDER")
+            .env_overrides("TOMLENV_BUILDER_OVERRIDE")
+            .build()
+            .expect("Unable to build Environments");
+        let current = envs.current().expect("Current is not Development!");
+        assert_eq!(current.name, "Development");
+        assert_eq!(current.key.as_deref(), Some("default-key"));
+        assert_eq!(
+            envs.get(&Environment::Prod).map(|e| e.name.as_str()),
+            Some("Overridden")
+        );
+    }
+
+    #[test]
+    fn build_deserialize_only() {
+        #[derive(Debug, Deserialize)]
+        struct ReadOnlyEnv {
+            name: String,
+            port: u16,
+        }
+
+        env::set_var("TOMLENV_BUILDER_READ_ONLY_PROD_PORT", "443");
+        let envs: Environments<Environment, ReadOnlyEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(
+                "[defaults]\nport = 80\n\n[envs.prod]\nname = \"Production\"\n",
+            ))
+            .deny_unknown_fields(true)
+            .env_overrides("TOMLENV_BUILDER_READ_ONLY")
+            .build()
+            .expect("Unable to build Environments");
+        let prod = envs.get(&Environment::Prod).expect("prod is defined");
+        assert_eq!(prod.name, "Production");
+        assert_eq!(prod.port, 443);
+    }
+
+    #[test]
+    fn build_without_defaults() {
+        let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(TOML))
+            .with_defaults(false)
+            .build()
+            .expect("Unable to build Environments");
+        assert_eq!(
+            envs.get(&Environment::Dev).and_then(|e| e.key.as_deref()),
+            None
+        );
+    }
+
+    #[test]
+    fn build_missing_path() {
+        let result: crate::Result<Environments<Environment, RuntimeEnv>> =
+            EnvironmentsBuilder::new()
+                .path(env::temp_dir().join("tomlenv-builder-missing.toml"))
+                .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn build_deny_unknown_fields() {
+        let toml = format!("{TOML}nmae = \"Typo\"\n");
+        let result: crate::Result<Environments<Environment, RuntimeEnv>> =
+            EnvironmentsBuilder::new()
+                .reader(Cursor::new(toml.clone()))
+                .deny_unknown_fields(true)
+                .build();
+        let err = result.expect_err("nmae is not a RuntimeEnv field");
+        assert_eq!(err.reason(), "unknown fields: envs.dev.nmae");
+
+        let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(toml))
+            .build()
+            .expect("Unknown fields are ignored by default");
+        assert_eq!(envs.len(), 2);
+    }
+
+    #[test]
+    fn build_deny_unknown_fields_overrides() {
+        env::set_var("TOMLENV_BUILDER_STRICT_PROD_KEY", "override-key");
+        env::set_var("TOMLENV_BUILDER_STRICT_PROD_KYE", "typo");
+        let result: crate::Result<Environments<Environment, RuntimeEnv>> =
+            EnvironmentsBuilder::new()
+                .reader(Cursor::new(TOML))
+                .env_overrides("TOMLENV_BUILDER_STRICT")
+                .deny_unknown_fields(true)
+                .build();
+        let err = result.expect_err("kye is not a RuntimeEnv field");
+        assert_eq!(err.reason(), "unknown fields: envs.prod.kye");
+
+        env::remove_var("TOMLENV_BUILDER_STRICT_PROD_KYE");
+        let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(TOML))
+            .env_overrides("TOMLENV_BUILDER_STRICT")
+            .deny_unknown_fields(true)
+            .build()
+            .expect("Unable to build Environments");
+        assert_eq!(
+            envs.get(&Environment::Prod).and_then(|e| e.key.as_deref()),
+            Some("override-key")
+        );
+        env::remove_var("TOMLENV_BUILDER_STRICT_PROD_KEY");
+    }
+
+    #[test]
+    fn build_deny_unknown_fields_serde_attrs() {
+        #[derive(Debug, Deserialize, Serialize)]
+        struct AttrEnv {
+            #[serde(alias = "title")]
+            name: String,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            key: Option<String>,
+        }
+
+        let toml = r#"[defaults]
+kye = "typo"
+
+[envs.prod]
+title = "Production"
+
+[envs.dev]
+name = "Development"
+"#;
+        let result: crate::Result<Environments<Environment, AttrEnv>> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(toml))
+            .deny_unknown_fields(true)
+            .build();
+        let err = result.expect_err("kye is not an AttrEnv field");
+        assert_eq!(err.reason(), "unknown fields: defaults.kye");
+
+        let envs: Environments<Environment, AttrEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(toml.replace("kye", "key")))
+            .deny_unknown_fields(true)
+            .build()
+            .expect("Aliased and skipped fields are known");
+        assert_eq!(
+            envs.get(&Environment::Prod).map(|e| e.name.as_str()),
+            Some("Production")
+        );
+    }
+
+    #[test]
+    fn build_table_name() {
+        let toml = TOML.replace("[envs.", "[environments.");
+        let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(toml))
+            .table_name("environments")
+            .build()
+            .expect("Unable to build Environments");
+        assert_eq!(
+            envs.get(&Environment::Dev).and_then(|e| e.key.as_deref()),
+            Some("default-key")
+        );
+    }
+
+    #[test]
+    fn build_expand_env() {
+        env::set_var("TOMLENV_BUILDER_SECRET", "expanded-key");
+        let toml = TOML.replace("abcd-123-efg-45", "${TOMLENV_BUILDER_SECRET}");
+        let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(toml.clone()))
+            .expand_env(true)
+            .build()
+            .expect("Unable to build Environments");
+        assert_eq!(
+            envs.get(&Environment::Prod).and_then(|e| e.key.as_deref()),
+            Some("expanded-key")
+        );
+
+        let result: crate::Result<Environments<Environment, RuntimeEnv>> =
+            EnvironmentsBuilder::new()
+                .reader(Cursor::new(toml.replace("SECRET", "UNSET")))
+                .expand_env(true)
+                .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn build_expand_env_special_chars() {
+        env::set_var(
+            "TOMLENV_BUILDER_SPECIAL",
+            "a \"quoted\" \\path\n[envs.evil]",
+        );
+        let toml = format!(
+            "# costs $5, ${{TOMLENV_BUILDER_UNSET}}\n{}",
+            TOML.replace("abcd-123-efg-45", "$TOMLENV_BUILDER_SPECIAL")
+        );
+        let envs: Environments<Environment, RuntimeEnv> = EnvironmentsBuilder::new()
+            .reader(Cursor::new(toml))
+            .expand_env(true)
+            .build()
+            .expect("Unable to build Environments");
+        assert_eq!(envs.len(), 2);
+        assert_eq!(
+            envs.get(&Environment::Prod).and_then(|e| e.key.as_deref()),
+            Some("a \"quoted\" \\path\n[envs.evil]")
+        );
+    }
+}
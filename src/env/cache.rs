@@ -0,0 +1,48 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` current environment cache
+use std::{
+    fmt,
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// The value last read from a selection variable, and the environment it was
+/// converted to
+pub(crate) struct CurrentCache<S>(Mutex<Option<(String, S)>>);
+
+impl<S> CurrentCache<S> {
+    /// Lock the cache
+    pub(crate) fn lock(&self) -> MutexGuard<'_, Option<(String, S)>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Empty the cache
+    pub(crate) fn clear(&self) {
+        *self.lock() = None;
+    }
+}
+
+impl<S> Default for CurrentCache<S> {
+    fn default() -> Self {
+        Self(Mutex::new(None))
+    }
+}
+
+/// A clone starts with an empty cache.
+impl<S> Clone for CurrentCache<S> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<S> fmt::Debug for CurrentCache<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CurrentCache")
+    }
+}
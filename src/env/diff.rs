@@ -0,0 +1,91 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` environments comparison
+use toml::Value;
+
+/// A difference between two `Environments`.  See `Environments::diff`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum EnvDiff<S> {
+    /// The environment is only in the new environments
+    Added(S),
+    /// The environment is only in the old environments
+    Removed(S),
+    /// The environment is in both, but the values of the given dotted keys
+    /// differ
+    Changed {
+        /// The environment
+        environment: S,
+        /// The keys that were added, removed, or changed
+        keys: Vec<String>,
+    },
+}
+
+/// Collect the dotted paths of the keys whose values differ between `old`
+/// and `new`, descending into tables present in both.
+pub(crate) fn changed_keys(old: &Value, new: &Value, path: &str) -> Vec<String> {
+    let key_path = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+
+    match (old, new) {
+        (Value::Table(old), Value::Table(new)) => {
+            let mut keys: Vec<String> = old
+                .keys()
+                .chain(new.keys().filter(|key| !old.contains_key(*key)))
+                .flat_map(|key| match (old.get(key), new.get(key)) {
+                    (Some(old), Some(new)) => changed_keys(old, new, &key_path(key)),
+                    _ => vec![key_path(key)],
+                })
+                .collect();
+            keys.sort();
+            keys
+        }
+        (old, new) if old == new => Vec::new(),
+        _ => vec![path.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::changed_keys;
+    use toml::Value;
+
+    #[test]
+    fn changed() {
+        let old: Value = toml::from_str(
+            r#"name = "Production"
+key = "old-key"
+removed = 1
+[nested]
+a = 1
+b = 2
+"#,
+        )
+        .expect("valid toml");
+        let new: Value = toml::from_str(
+            r#"name = "Production"
+key = "new-key"
+added = true
+[nested]
+a = 1
+b = 3
+"#,
+        )
+        .expect("valid toml");
+        assert_eq!(
+            changed_keys(&old, &new, ""),
+            vec!["added", "key", "nested.b", "removed"]
+        );
+        assert!(changed_keys(&old, &old, "").is_empty());
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` encoded config values
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+
+/// The standard base64 alphabet
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Bytes stored in TOML as a standard, padded base64 string.
+///
+/// The string is decoded when loading and encoded again when saving.  Wrap it
+/// in `Secret` to also keep the bytes out of logs.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::{Base64, Environment, Environments, Result};
+/// # use serde::{Deserialize, Serialize};
+/// #
+/// # fn foo() -> Result<()> {
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct RuntimeEnv {
+///     key: Base64,
+/// }
+///
+/// let envs: Environments<Environment, RuntimeEnv> =
+///     "[envs.prod]\nkey = \"aGVsbG8=\"\n".parse()?;
+/// assert_eq!(envs.current_from_value("prod")?.key.as_bytes(), b"hello");
+/// #   Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Base64(Vec<u8>);
+
+impl Base64 {
+    /// The decoded bytes.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Consume the wrapper, returning the decoded bytes.
+    #[must_use]
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl From<Vec<u8>> for Base64 {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Base64 {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<'de> Deserialize<'de> for Base64 {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let encoded = String::deserialize(deserializer)?;
+        decode(&encoded)
+            .map(Self)
+            .ok_or_else(|| de::Error::custom("invalid base64"))
+    }
+}
+
+impl Serialize for Base64 {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&encode(&self.0))
+    }
+}
+
+/// Encode bytes as padded base64
+fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() / 3 * 4 + 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode padded base64, or `None` if it is invalid
+fn decode(encoded: &str) -> Option<Vec<u8>> {
+    let chunks = encoded.as_bytes().chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return None;
+    }
+
+    let count = chunks.len();
+    let mut bytes = Vec::with_capacity(count * 3);
+    for (index, chunk) in chunks.enumerate() {
+        let last = index + 1 == count;
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+
+        let mut n: u32 = 0;
+        for &c in &chunk[..4 - padding] {
+            let value = ALPHABET.iter().position(|&a| a == c)?;
+            n = n << 6 | u32::try_from(value).ok()?;
+        }
+        n <<= 6 * padding;
+        let decoded = n.to_be_bytes();
+        bytes.extend_from_slice(&decoded[1..4 - padding]);
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, encode, Base64};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Config {
+        key: Base64,
+    }
+
+    #[test]
+    fn round_trip() {
+        for text in ["", "f", "fo", "foo", "foob", "fooba", "foobar"] {
+            let encoded = encode(text.as_bytes());
+            assert_eq!(decode(&encoded).as_deref(), Some(text.as_bytes()));
+        }
+        assert_eq!(encode(b"foobar"), "Zm9vYmFy");
+        assert_eq!(encode(b"fo"), "Zm8=");
+        assert_eq!(decode("Zm8"), None);
+        assert_eq!(decode("Zm=8"), None);
+        assert_eq!(decode("Zm8=Zm8="), None);
+        assert_eq!(decode("Zm*="), None);
+    }
+
+    #[test]
+    fn base64() {
+        let config: Config = toml::from_str("key = \"aGVsbG8=\"").expect("Unable to decode TOML");
+        assert_eq!(config.key.as_bytes(), b"hello");
+        assert_eq!(
+            toml::to_string(&config).ok().as_deref(),
+            Some("key = \"aGVsbG8=\"\n")
+        );
+
+        let err = toml::from_str::<Config>("key = \"not base64\"")
+            .expect_err("Invalid base64 should not decode");
+        assert!(err.to_string().contains("invalid base64"));
+        assert!(err.to_string().contains("key"));
+    }
+}
@@ -7,14 +7,21 @@
 // modified, or distributed except according to those terms.
 
 //! `tomlenv` default environment hierarchy implementation.
-use crate::error::{Error, Result};
+use crate::{
+    error::{Error, Result},
+    hierarchy::Enumerable,
+};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::TryFrom;
 use std::fmt;
+use std::str::FromStr;
 
 /// A fairly standard environment hierarchy for use with `Environments`.
 /// Prod -> Stage -> Test -> Dev -> Local
-#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+///
+/// Parsing also accepts the aliases `production`, `staging`, `qa`, and
+/// `development`.  The default environment is `Local`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub enum Environment {
     /// Production
     Prod,
@@ -25,9 +32,103 @@ pub enum Environment {
     /// Development
     Dev,
     /// Local
+    #[default]
     Local,
 }
 
+impl Environment {
+    /// Every environment, in hierarchy order.
+    #[must_use]
+    pub const fn all() -> [Environment; 5] {
+        [
+            Environment::Prod,
+            Environment::Stage,
+            Environment::Test,
+            Environment::Dev,
+            Environment::Local,
+        ]
+    }
+
+    /// The canonical string form of this environment.
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match *self {
+            Environment::Prod => "prod",
+            Environment::Stage => "stage",
+            Environment::Test => "test",
+            Environment::Dev => "dev",
+            Environment::Local => "local",
+        }
+    }
+
+    /// The position of this environment in the hierarchy, from `Prod` (0) to
+    /// `Local` (4).  This matches the `Ord` ordering.
+    #[must_use]
+    pub const fn rank(&self) -> u8 {
+        match *self {
+            Environment::Prod => 0,
+            Environment::Stage => 1,
+            Environment::Test => 2,
+            Environment::Dev => 3,
+            Environment::Local => 4,
+        }
+    }
+
+    /// The next more restrictive environment in the hierarchy, if any.
+    #[must_use]
+    pub const fn parent(&self) -> Option<Environment> {
+        match *self {
+            Environment::Prod => None,
+            Environment::Stage => Some(Environment::Prod),
+            Environment::Test => Some(Environment::Stage),
+            Environment::Dev => Some(Environment::Test),
+            Environment::Local => Some(Environment::Dev),
+        }
+    }
+
+    /// Is this the production environment?
+    #[must_use]
+    pub const fn is_prod(&self) -> bool {
+        matches!(self, Environment::Prod)
+    }
+
+    /// Is this the stage environment?
+    #[must_use]
+    pub const fn is_stage(&self) -> bool {
+        matches!(self, Environment::Stage)
+    }
+
+    /// Is this the test environment?
+    #[must_use]
+    pub const fn is_test(&self) -> bool {
+        matches!(self, Environment::Test)
+    }
+
+    /// Is this the development environment?
+    #[must_use]
+    pub const fn is_dev(&self) -> bool {
+        matches!(self, Environment::Dev)
+    }
+
+    /// Is this the local environment?
+    #[must_use]
+    pub const fn is_local(&self) -> bool {
+        matches!(self, Environment::Local)
+    }
+}
+
+impl Enumerable for Environment {
+    fn all() -> Vec<Self> {
+        Environment::all().to_vec()
+    }
+}
+
+impl AsRef<str> for Environment {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
 impl<'de> Deserialize<'de> for Environment {
     fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
     where
@@ -59,20 +160,13 @@ impl Serialize for Environment {
     where
         S: Serializer,
     {
-        serializer.serialize_str(&self.to_string())
+        serializer.serialize_str(self.as_str())
     }
 }
 
 impl fmt::Display for Environment {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let env = match *self {
-            Environment::Prod => "prod",
-            Environment::Stage => "stage",
-            Environment::Test => "test",
-            Environment::Dev => "dev",
-            Environment::Local => "local",
-        };
-        write!(f, "{env}")
+        write!(f, "{}", self.as_str())
     }
 }
 
@@ -80,29 +174,30 @@ impl TryFrom<&str> for Environment {
     type Error = Error;
 
     fn try_from(env: &str) -> Result<Self> {
-        match env {
-            "prod" => Ok(Environment::Prod),
-            "stage" => Ok(Environment::Stage),
-            "test" => Ok(Environment::Test),
-            "dev" => Ok(Environment::Dev),
+        match &env.trim().to_lowercase()[..] {
+            "prod" | "production" => Ok(Environment::Prod),
+            "stage" | "staging" => Ok(Environment::Stage),
+            "test" | "qa" => Ok(Environment::Test),
+            "dev" | "development" => Ok(Environment::Dev),
             "local" => Ok(Environment::Local),
             _ => Err(Error::invalid_runtime_environment(env)),
         }
     }
 }
 
+impl FromStr for Environment {
+    type Err = Error;
+
+    fn from_str(env: &str) -> Result<Self> {
+        Self::try_from(env)
+    }
+}
+
 impl TryFrom<String> for Environment {
     type Error = Error;
 
     fn try_from(env: String) -> Result<Self> {
-        match &env[..] {
-            "prod" => Ok(Environment::Prod),
-            "stage" => Ok(Environment::Stage),
-            "test" => Ok(Environment::Test),
-            "dev" => Ok(Environment::Dev),
-            "local" => Ok(Environment::Local),
-            _ => Err(Error::invalid_runtime_environment(&env)),
-        }
+        Self::try_from(&env[..])
     }
 }
 
@@ -120,6 +215,67 @@ mod test {
         assert_eq!(Environment::Local.to_string(), "local");
     }
 
+    #[test]
+    fn as_str() {
+        assert_eq!(Environment::Prod.as_str(), "prod");
+        assert_eq!(Environment::Stage.as_str(), "stage");
+        assert_eq!(Environment::Test.as_str(), "test");
+        assert_eq!(Environment::Dev.as_str(), "dev");
+        assert_eq!(Environment::Local.as_ref(), "local");
+    }
+
+    #[test]
+    fn all() {
+        let all = Environment::all();
+        assert_eq!(
+            all,
+            [
+                Environment::Prod,
+                Environment::Stage,
+                Environment::Test,
+                Environment::Dev,
+                Environment::Local,
+            ]
+        );
+        assert!(all.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn rank() {
+        let all = Environment::all();
+        for (expected, env) in (0..).zip(all.iter()) {
+            assert_eq!(env.rank(), expected);
+        }
+        assert!(all
+            .windows(2)
+            .all(|pair| (pair[0] < pair[1]) == (pair[0].rank() < pair[1].rank())));
+    }
+
+    #[test]
+    fn parent() {
+        assert_eq!(Environment::Local.parent(), Some(Environment::Dev));
+        assert_eq!(Environment::Dev.parent(), Some(Environment::Test));
+        assert_eq!(Environment::Test.parent(), Some(Environment::Stage));
+        assert_eq!(Environment::Stage.parent(), Some(Environment::Prod));
+        assert_eq!(Environment::Prod.parent(), None);
+    }
+
+    #[test]
+    fn predicates() {
+        assert!(Environment::Prod.is_prod());
+        assert!(!Environment::Prod.is_local());
+        assert!(Environment::Stage.is_stage());
+        assert!(Environment::Test.is_test());
+        assert!(Environment::Dev.is_dev());
+        assert!(Environment::Local.is_local());
+        assert!(!Environment::Local.is_prod());
+    }
+
+    #[test]
+    fn default() {
+        assert_eq!(Environment::default(), Environment::Local);
+    }
+
     #[test]
     fn convert() {
         match Environment::try_from("prod") {
@@ -143,4 +299,47 @@ mod test {
             Err(_) => assert!(true, "'blah' failed to convert properly"),
         }
     }
+
+    #[test]
+    fn convert_case_insensitive() {
+        assert_eq!(Environment::try_from("PROD").ok(), Some(Environment::Prod));
+        assert_eq!(
+            Environment::try_from(" prod ").ok(),
+            Some(Environment::Prod)
+        );
+        assert_eq!(Environment::try_from("Prod").ok(), Some(Environment::Prod));
+        assert_eq!(
+            Environment::try_from("Local\n".to_string()).ok(),
+            Some(Environment::Local)
+        );
+        assert_eq!(Environment::Prod.to_string(), "prod");
+    }
+
+    #[test]
+    fn convert_aliases() {
+        assert_eq!(
+            Environment::try_from("production").ok(),
+            Some(Environment::Prod)
+        );
+        assert_eq!(
+            Environment::try_from("Staging").ok(),
+            Some(Environment::Stage)
+        );
+        assert_eq!(Environment::try_from("qa").ok(), Some(Environment::Test));
+        assert_eq!(
+            Environment::try_from("development").ok(),
+            Some(Environment::Dev)
+        );
+        assert_eq!(Environment::Dev.to_string(), "dev");
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("prod".parse::<Environment>().ok(), Some(Environment::Prod));
+        assert_eq!(
+            "local".parse::<Environment>().ok(),
+            Some(Environment::Local)
+        );
+        assert!("blah".parse::<Environment>().is_err());
+    }
 }
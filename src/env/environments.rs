@@ -7,18 +7,59 @@
 // modified, or distributed except according to those terms.
 
 //! `tomlenv` environments configuration
-use crate::error::{Error, Result};
+#[cfg(any(feature = "json", feature = "yaml"))]
+use crate::env::nullable::Nullable;
+#[cfg(feature = "indexmap")]
+use crate::env::order;
+use crate::{
+    env::{
+        cache::CurrentCache, diff, value, var::process_vars, EnvDiff, Environment, Merge,
+        StdVarReader, VarReader,
+    },
+    error::{ErrSource, Error, Result},
+    hierarchy::{Enumerable, Hierarchy, Key},
+};
+#[cfg(feature = "clap")]
 use clap::ArgMatches;
-use serde::{de::DeserializeOwned, ser::Serialize, Deserialize, Serialize as Ser};
+#[cfg(feature = "gzip")]
+use flate2::bufread::GzDecoder;
+#[cfg(feature = "indexmap")]
+use indexmap::{map, IndexMap};
+use serde::{
+    de::{self, DeserializeOwned, Deserializer, IntoDeserializer, MapAccess, Visitor},
+    ser::Serialize,
+    Deserialize, Serialize as Ser,
+};
+#[cfg(not(feature = "indexmap"))]
+use std::collections::{btree_map as map, BTreeMap};
+#[cfg(feature = "http")]
+use std::time::Duration;
 use std::{
-    collections::BTreeMap,
     convert::TryFrom,
-    env,
+    env::{self, VarError},
+    fmt,
     fs::File,
-    io::Read,
-    path::{Path, PathBuf},
+    io::{BufReader, Read, Write},
+    iter::FromIterator,
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
 };
 
+/// The variable used by `Environments::load` to locate the TOML file
+const PATH_VAR: &str = "TOMLENV_PATH";
+/// The timeout for `Environments::from_url`
+#[cfg(feature = "http")]
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The map backing `Environments`
+#[cfg(not(feature = "indexmap"))]
+type Map<S, T> = BTreeMap<S, T>;
+/// The map backing `Environments`
+#[cfg(feature = "indexmap")]
+type Map<S, T> = IndexMap<S, T>;
+
 /// Hold environment specific data as a map from your environment hierarchy key to data struct
 /// containg the config for that particular environment.
 ///
@@ -92,242 +133,2559 @@ use std::{
 /// #   Ok(())
 /// # }
 /// ```
+///
+/// Each environment may only be declared once.  Two tables whose names
+/// convert to the same environment, e.g. `[envs.prod]` and
+/// `[envs.production]`, are an error.
+///
+/// # Defaults
+///
+/// Values shared by every environment can be placed in an optional top-level
+/// `[defaults]` table.  These are merged under each `[envs.*]` table when
+/// loading, with the values in the environment table taking precedence.
+///
+/// ```toml
+/// [defaults]
+/// name = "Unnamed"
+///
+/// [envs.prod]
+/// name = "Production"
+/// key = "abcd-123-efg-45"
+///
+/// [envs.dev]
+/// ```
+///
+/// # Inheritance
+///
+/// An environment table can name another environment with an `inherits` key.
+/// Any values unset in the environment are then taken from the named one,
+/// before the `[defaults]` table is applied.
+///
+/// ```toml
+/// [envs.prod]
+/// name = "Production"
+/// key = "abcd-123-efg-45"
+///
+/// [envs.stage]
+/// inherits = "prod"
+/// name = "Stage"
+/// ```
+///
+/// # Ordering
+///
+/// Environments are always serialized in the `Ord` order of the hierarchy,
+/// whatever order they were declared or inserted in, so writing the same
+/// environments always produces the same TOML.
+///
+/// With the `indexmap` feature, environments instead keep the order they
+/// were declared or inserted in, and the hierarchy only needs `Hash` and `Eq`
+/// rather than `Ord`.  The environments above one in the hierarchy are then
+/// those declared before it.
 #[derive(Clone, Debug, Deserialize, Ser)]
 pub struct Environments<S, T>
 where
-    S: Ord,
+    S: Key,
 {
     /// A map of `Environment` to struct
-    envs: BTreeMap<S, T>,
+    #[serde(
+        deserialize_with = "unique_envs",
+        bound(deserialize = "S: Deserialize<'de>, T: Deserialize<'de>")
+    )]
+    envs: Map<S, T>,
+    /// The variable used to select the current environment, `env` if unset
+    #[serde(skip)]
+    var: Option<String>,
+    /// The environment pinned with `select`, if any
+    #[serde(skip, default = "Option::default")]
+    selected: Option<S>,
+    /// The environment last converted from the selection variable
+    #[serde(skip, default = "CurrentCache::default")]
+    cache: CurrentCache<S>,
 }
 
 impl<S, T> Environments<S, T>
 where
-    T: DeserializeOwned + Serialize,
-    S: DeserializeOwned + Serialize + Ord + PartialOrd + TryFrom<String>,
+    T: DeserializeOwned,
+    S: Hierarchy,
 {
     /// Load the environments from a path.
     ///
     /// # Errors
     ///
+    #[track_caller]
     pub fn from_path(path: &Path) -> Result<Self> {
-        match File::open(path) {
-            Ok(mut file) => {
-                let mut buffer = String::new();
-                let _ = file.read_to_string(&mut buffer)?;
-                Ok(toml::from_str(&buffer)?)
-            }
-            Err(e) => {
-                eprintln!("Unable to read '{}'", path.display());
-                Err(e.into())
+        Self::from_str(&read_path(path)?)
+    }
+
+    /// Load and merge the environments from several paths, in order.  An
+    /// environment defined in a later file replaces the same environment from
+    /// an earlier file.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_paths(paths: &[&Path]) -> Result<Self> {
+        let mut envs = Map::new();
+        for path in paths {
+            envs.extend(Self::from_path(path)?.envs);
+        }
+        Ok(Self::from(envs))
+    }
+
+    /// Load the environments from the path in the `TOMLENV_PATH` environment
+    /// variable, or from `env.toml` if it is not set.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn load() -> Result<Self> {
+        Self::from_path(&default_path())
+    }
+
+    /// Load the environments from the file named `file_name` in `start` or
+    /// the nearest of its ancestor directories that contains one.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_path_ancestors(start: &Path, file_name: &str) -> Result<Self> {
+        let mut searched = Vec::new();
+        for dir in start.ancestors() {
+            let path = dir.join(file_name);
+            if path.is_file() {
+                return Self::from_path(&path);
             }
+            searched.push(dir.to_path_buf());
         }
+        Err(Error::not_found_in_ancestors(file_name, &searched))
+    }
+
+    /// Reload the environments from a path in place, keeping the selection
+    /// variable.  The environments are left unchanged if the path cannot be
+    /// loaded.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn reload_from_path(&mut self, path: &Path) -> Result<()> {
+        self.envs = Self::from_path(path)?.envs;
+        Ok(())
+    }
+
+    /// Load the environments from a TOML string.
+    ///
+    /// # Errors
+    ///
+    #[allow(clippy::should_implement_trait)]
+    #[track_caller]
+    pub fn from_str(toml: &str) -> Result<Self> {
+        Ok(Self::parse(toml::from_str(toml)?, "envs", true)?
+            .in_declared_order(&mut toml::Deserializer::new(toml), "envs"))
+    }
+
+    /// Load the environments from TOML bytes.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        Self::from_str(std::str::from_utf8(bytes)?)
     }
 
     /// Load the environments from a reader.
     ///
     /// # Errors
     ///
+    #[track_caller]
     pub fn from_reader<R>(reader: &mut R) -> Result<Self>
     where
         R: Read,
     {
         let mut buffer = String::new();
         let _ = reader.read_to_string(&mut buffer)?;
-        Ok(toml::from_str(&buffer)?)
+        Self::from_str(&buffer)
     }
 
-    /// Get the current environment
+    /// Load only the environment selected by the given variable from a
+    /// reader.  The other environments are left as TOML, so only the selected
+    /// config is deserialized.
     ///
     /// # Errors
     ///
-    pub fn current(&self) -> Result<&T> {
-        self.current_from("env")
+    #[track_caller]
+    pub fn current_lazy_from_reader<R>(reader: &mut R, var: &str) -> Result<T>
+    where
+        R: Read,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::current_lazy_from_reader_with(reader, var, &StdVarReader)
     }
 
-    /// Get the current environment from the given variable
+    /// Load only the environment selected by the given variable from a
+    /// reader, as `current_lazy_from_reader` does, reading the variable with
+    /// the given `VarReader`
     ///
     /// # Errors
     ///
-    pub fn current_from(&self, var: &'static str) -> Result<&T> {
-        let environment = TryFrom::try_from(env::var(var)?)
-            .map_err(|_e| Error::invalid_current_environment(var))?;
-        self.envs
-            .get(&environment)
-            .ok_or_else(|| Error::invalid_current_environment(var))
+    #[track_caller]
+    pub fn current_lazy_from_reader_with<R, V>(reader: &mut R, var: &str, vars: &V) -> Result<T>
+    where
+        R: Read,
+        V: VarReader,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let value = read_var(var, vars)?;
+        let environment = Self::environment(var, &value)?;
+
+        let mut buffer = String::new();
+        let _ = reader.read_to_string(&mut buffer)?;
+        let document = document(toml::from_str(&buffer)?, "envs", true)?;
+        let Some(toml::Value::Table(envs)) = document.get("envs") else {
+            return Err(Error::invalid_current_environment(var, &value));
+        };
+        for (name, config) in envs {
+            let key: Option<S> = toml::Value::String(name.clone()).try_into().ok();
+            if key.as_ref() == Some(&environment) {
+                return Ok(config.clone().try_into()?);
+            }
+        }
+        Err(Error::invalid_current_environment(var, &value))
     }
-}
 
-impl<'a, S, T> TryFrom<&'a ArgMatches<'a>> for Environments<S, T>
-where
-    T: DeserializeOwned + Serialize,
-    S: DeserializeOwned + Serialize + Ord + PartialOrd + TryFrom<String>,
-{
-    type Error = Error;
+    /// Load the config of the environment selected by the given variable from
+    /// a directory with a file per environment.  The environment's file, e.g.
+    /// `prod.toml`, is merged over the shared config in `base.toml`.  An
+    /// environment name that is not a plain file name, e.g. `../secrets`, is
+    /// rejected.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_layered(dir: &Path, var: &str) -> Result<T>
+    where
+        S: fmt::Display,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        Self::from_layered_with(dir, var, &StdVarReader)
+    }
 
-    fn try_from(matches: &'a ArgMatches<'a>) -> Result<Self> {
-        let env_path = if let Some(env_path) = matches.value_of("env_path") {
-            PathBuf::from(env_path).join("env.toml")
-        } else {
-            PathBuf::from("env.toml")
-        };
+    /// Load the layered config of the environment selected by the given
+    /// variable, as `from_layered` does, reading the variable with the given
+    /// `VarReader`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_layered_with<V>(dir: &Path, var: &str, vars: &V) -> Result<T>
+    where
+        V: VarReader,
+        S: fmt::Display,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let value = read_var(var, vars)?;
+        let environment = Self::environment(var, &value)?;
 
-        Environments::from_path(env_path.as_path())
+        // The name must stay within `dir`, e.g. a `StringEnv` of `../secrets`.
+        let name = environment.to_string();
+        let mut components = Path::new(&name).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(file)), None)
+                if file == name.as_str() && !name.contains('\\') => {}
+            _ => return Err(Error::invalid_current_environment(var, &value)),
+        }
+
+        let base: toml::value::Table = toml::from_str(&read_path(&dir.join("base.toml"))?)?;
+        let path = dir.join(format!("{name}.toml"));
+        let mut layer: toml::value::Table = toml::from_str(&read_path(&path)?)?;
+        value::merge_tables(&mut layer, &base);
+        Ok(toml::Value::Table(layer).try_into()?)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::Environments;
-    use crate::{env::Environment, error::Result};
-    use clap::{App, Arg};
-    use dirs;
-    use getset::Getters;
-    use serde::{Deserialize, Serialize};
-    use std::{
-        collections::BTreeMap,
-        convert::TryFrom,
-        env,
-        fs::{remove_file, OpenOptions},
-        io::{BufWriter, Cursor, Write},
-    };
-    use toml;
+    /// Parse the environments from a TOML document, reading them from the
+    /// given top-level table and optionally merging any `defaults` table under
+    /// each environment
+    #[track_caller]
+    pub(crate) fn parse(document: toml::Value, table_name: &str, defaults: bool) -> Result<Self> {
+        Ok(self::document(document, table_name, defaults)?.try_into()?)
+    }
 
-    const TOMLENV: &str = "TOMLENV";
-    const EXPECTED_TOML_STR: &str = r#"[envs.prod]
-name = "Production"
-key = "abcd-123-efg-45"
+    /// Put the environments back in the order they were declared in the given
+    /// table of the source, which converting through `toml::Value` loses.  The
+    /// source has already been parsed, so the order can always be read.
+    #[cfg(feature = "indexmap")]
+    pub(crate) fn in_declared_order<'de, D>(mut self, source: D, table_name: &str) -> Self
+    where
+        D: Deserializer<'de>,
+    {
+        let names = order::declared_order(source, table_name).unwrap_or_default();
+        self.reorder(names.into_iter().filter_map(|name| S::try_from(name).ok()));
+        self
+    }
 
-[envs.stage]
-name = "Stage"
+    /// Environments are kept in hierarchy order without the `indexmap`
+    /// feature, so there is no declaration order to restore
+    #[cfg(not(feature = "indexmap"))]
+    pub(crate) fn in_declared_order<'de, D>(self, _source: D, _table_name: &str) -> Self
+    where
+        D: Deserializer<'de>,
+    {
+        self
+    }
 
-[envs.test]
-name = "Test"
+    /// Deserialize the environments from a prepared document, rejecting any
+    /// fields that are not part of the config type.  `declared` is the
+    /// document before any `inherits` or `defaults` were merged, and is used
+    /// to report each unknown field where it was written.
+    #[track_caller]
+    pub(crate) fn parse_strict(document: toml::Value, declared: &toml::Value) -> Result<Self> {
+        let mut ignored = Vec::new();
+        let envs = serde_ignored::deserialize(document, |path| {
+            ignored.push(value::ignored_keys(&path));
+        })?;
+        let unknown = value::unknown_fields(declared, &ignored);
+        if unknown.is_empty() {
+            Ok(envs)
+        } else {
+            Err(Error::unknown_fields(&unknown))
+        }
+    }
+}
 
-[envs.dev]
-name = "Development"
+impl<S, T> Environments<S, T>
+where
+    T: Serialize,
+    S: Key + Serialize,
+{
+    /// Serialize the environments to a TOML string.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn to_string(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
 
-[envs.local]
-name = "Local"
-"#;
+    /// Write the environments to a path, creating or truncating the file.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn to_path(&self, path: &Path) -> Result<()> {
+        let toml = self.to_string()?;
+        match File::create(path).and_then(|mut file| file.write_all(toml.as_bytes())) {
+            Ok(()) => Ok(()),
+            Err(e) => Err(Error::write_path(path, e)),
+        }
+    }
 
-    #[derive(Debug, Deserialize, Getters, Serialize)]
-    struct RuntimeEnv {
-        #[get]
-        name: String,
-        #[get]
-        key: Option<String>,
+    /// Write the environments to a writer.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn to_writer<W>(&self, writer: &mut W) -> Result<()>
+    where
+        W: Write,
+    {
+        let toml = self.to_string()?;
+        Ok(writer.write_all(toml.as_bytes())?)
     }
+}
 
-    fn try_decode(toml: &str) -> Result<Environments<Environment, RuntimeEnv>> {
-        let mut cursor = Cursor::new(toml);
-        Ok(Environments::from_reader(&mut cursor)?)
+impl<S, T> Environments<S, T>
+where
+    T: DeserializeOwned + Serialize,
+    S: Hierarchy + Serialize,
+{
+    /// Apply overrides from environment variables named
+    /// `{PREFIX}_{ENV}_{FIELD}`, e.g. `APP_PROD_KEY` overrides the `key` field
+    /// of the `prod` environment.  Names are matched case-insensitively and
+    /// only top-level fields of each environment can be overridden.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn with_env_overrides(self, prefix: &str) -> Result<Self> {
+        let mut document = toml::Value::try_from(&self)?;
+        value::apply_env_overrides(&mut document, prefix, process_vars())?;
+        let mut envs: Self = document.try_into()?;
+        #[cfg(feature = "indexmap")]
+        envs.reorder(self.envs.into_iter().map(|(key, _)| key));
+        envs.var = self.var;
+        envs.selected = self.selected;
+        Ok(envs)
     }
+}
 
-    fn try_encode(environments: &Environments<Environment, RuntimeEnv>) -> Result<String> {
-        Ok(toml::to_string(environments)?)
+impl<S, T> Environments<S, T>
+where
+    S: Key + TryFrom<String>,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Get the current environment pinned with `select`, or else from the
+    /// selection variable, `env` unless set with
+    /// `EnvironmentsBuilder::var_name`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current(&self) -> Result<&T> {
+        self.current_with_reader(&StdVarReader)
     }
 
-    fn try_current(envs: &Environments<Environment, RuntimeEnv>, expected: &str) -> Result<()> {
-        let current = envs.current()?;
-        assert_eq!(current.name(), expected);
-        Ok(())
+    /// Get the key of the current environment
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_key(&self) -> Result<S>
+    where
+        S: Clone,
+    {
+        self.current_entry().map(|(environment, _)| environment)
     }
 
-    fn try_current_from(
-        var: &'static str,
-        envs: &Environments<Environment, RuntimeEnv>,
-        expected: &str,
-    ) -> Result<()> {
-        let current = envs.current_from(var)?;
-        assert_eq!(current.name(), expected);
-        Ok(())
+    /// Get the key and config of the current environment
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_entry(&self) -> Result<(S, &T)>
+    where
+        S: Clone,
+    {
+        let (environment, config) = self.require_current(&StdVarReader)?;
+        Ok((environment.clone(), config))
     }
 
-    fn test_cli() -> App<'static, 'static> {
-        App::new("env-from-app-matches")
-            .version("1")
-            .author("Yoda")
-            .about("command line for proxy config testing")
-            .arg(
-                Arg::with_name("env_path")
-                    .short("e")
-                    .long("envpath")
-                    .takes_value(true)
-                    .value_name("ENV_PATH"),
-            )
+    /// Get the current environment from the given variable
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from(&self, var: &str) -> Result<&T> {
+        self.current_from_with_reader(var, &StdVarReader)
     }
 
-    #[test]
-    fn decode() {
-        match try_decode(EXPECTED_TOML_STR) {
-            Ok(_) => assert!(true, "Successfully decode TOML to Environments"),
-            Err(_) => assert!(false, "Unable to decode TOML to Environments!"),
-        }
+    /// Get the environment with the given name, without reading any
+    /// environment variable.  See `get_by_name`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from_value(&self, value: &str) -> Result<&T> {
+        self.get_by_name(value)
     }
 
-    #[test]
-    fn encode() {
-        let mut envs = BTreeMap::new();
-        let prod = RuntimeEnv {
-            name: "Production".to_string(),
-            key: Some("abcd-123-efg-45".to_string()),
-        };
-        let stage = RuntimeEnv {
-            name: "Stage".to_string(),
-            key: None,
-        };
-        let test = RuntimeEnv {
-            name: "Test".to_string(),
-            key: None,
-        };
-        let dev = RuntimeEnv {
-            name: "Development".to_string(),
-            key: None,
+    /// Get the environment with the given name, converted with `TryFrom`,
+    /// e.g. from a request parameter
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn get_by_name(&self, name: &str) -> Result<&T> {
+        let environment = match S::try_from(name.to_string()) {
+            Ok(environment) => environment,
+            Err(e) => {
+                return Err(
+                    Error::invalid_environment(name).with_source(ErrSource::Environment(e.into()))
+                )
+            }
         };
-        let local = RuntimeEnv {
-            name: "Local".to_string(),
-            key: None,
+        let Some(config) = self.envs.get(&environment) else {
+            return Err(Error::invalid_environment(name));
         };
-        let _b = envs.insert(Environment::Prod, prod);
-        let _b = envs.insert(Environment::Stage, stage);
-        let _b = envs.insert(Environment::Test, test);
-        let _b = envs.insert(Environment::Dev, dev);
-        let _b = envs.insert(Environment::Local, local);
-
-        let environments = Environments { envs };
-
-        match try_encode(&environments) {
-            Ok(toml) => assert_eq!(toml, EXPECTED_TOML_STR, "TOML strings match"),
-            Err(_) => assert!(false, "Unable to encode Environments to TOML"),
-        }
+        Ok(config)
+    }
+
+    /// Get the current environment pinned with `select`, or else from the
+    /// selection variable, read with the given `VarReader`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_with_reader<R>(&self, reader: &R) -> Result<&T>
+    where
+        R: VarReader,
+    {
+        self.require_current(reader).map(|(_, config)| config)
+    }
+
+    /// Get the current environment from the given variable, read with the
+    /// given `VarReader`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from_with_reader<R>(&self, var: &str, reader: &R) -> Result<&T>
+    where
+        R: VarReader,
+    {
+        let value = match reader.var(var) {
+            Ok(value) => value,
+            Err(e) => return Err(Error::var(var, e)),
+        };
+        self.lookup(var, value).map(|(_, config)| config)
+    }
+
+    /// Get the current environment named in a file, e.g. `/etc/app-env`,
+    /// rather than in the selection variable.  Whitespace around the name is
+    /// ignored.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from_file(&self, path: &Path) -> Result<&T> {
+        let value = read_path(path)?;
+        self.lookup(&path.display().to_string(), value)
+            .map(|(_, config)| config)
+    }
+
+    /// Get the current environment, or `None` if none is pinned with `select`
+    /// and the selection variable is not set
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_opt(&self) -> Result<Option<&T>> {
+        Ok(self.find_current(&StdVarReader)?.map(|(_, config)| config))
+    }
+
+    /// Get the current environment, falling back to the given default
+    /// environment if none is pinned and the selection variable is not set
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_or(&self, default: &S) -> Result<&T> {
+        match self.find_current(&StdVarReader)? {
+            Some((_, config)) => Ok(config),
+            None => match self.envs.get(default) {
+                Some(config) => Ok(config),
+                None => Err(Error::invalid_default_environment()),
+            },
+        }
+    }
+
+    /// Get the current environment, falling back to the environment returned
+    /// by `f` if the selection variable is not set
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_or_else<F>(&self, f: F) -> Result<&T>
+    where
+        F: FnOnce() -> S,
+    {
+        match self.find_current(&StdVarReader)? {
+            Some((_, config)) => Ok(config),
+            None => match self.envs.get(&f()) {
+                Some(config) => Ok(config),
+                None => Err(Error::invalid_default_environment()),
+            },
+        }
+    }
+
+    /// Get the current environment, falling back to the default environment
+    /// if the selection variable is not set
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_or_default(&self) -> Result<&T>
+    where
+        S: Default,
+    {
+        self.current_or(&S::default())
+    }
+
+    /// Get the current environment, falling back to `debug` in debug builds
+    /// or `release` in release builds if the selection variable is not set
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_for_profile_with(&self, debug: &S, release: &S) -> Result<&T> {
+        self.current_or(if cfg!(debug_assertions) {
+            debug
+        } else {
+            release
+        })
+    }
+
+    /// Get the current environment, with any unset values inherited from the
+    /// more restrictive environments above it in the hierarchy.  See
+    /// `resolve`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_merged(&self) -> Result<T>
+    where
+        T: Clone + Merge,
+    {
+        let (environment, config) = self.require_current(&StdVarReader)?;
+        Ok(self.resolve(environment).unwrap_or_else(|| config.clone()))
+    }
+
+    /// Get the current environment followed by the more restrictive
+    /// environments above it in the hierarchy, nearest first, e.g. `dev`,
+    /// `test`, `stage`, then `prod`.  Only environments with config are
+    /// included.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_chain(&self) -> Result<Vec<(&S, &T)>> {
+        let (environment, _) = self.require_current(&StdVarReader)?;
+        Ok(self.chain(environment).into_iter().flatten().collect())
+    }
+
+    /// Get a mutable reference to the current environment, pinned with
+    /// `select` or from the selection variable
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_mut(&mut self) -> Result<&mut T> {
+        if let Some(selected) = &self.selected {
+            return match self.envs.get_mut(selected) {
+                Some(config) => Ok(config),
+                None => Err(Error::invalid_selected_environment()),
+            };
+        }
+        let var = self.var().to_string();
+        self.current_from_mut(&var)
+    }
+
+    /// Get a mutable reference to the current environment from the given
+    /// variable
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from_mut(&mut self, var: &str) -> Result<&mut T> {
+        self.current_from_mut_with_reader(var, &StdVarReader)
+    }
+
+    /// Get a mutable reference to the current environment from the given
+    /// variable, read with the given `VarReader`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from_mut_with_reader<R>(&mut self, var: &str, reader: &R) -> Result<&mut T>
+    where
+        R: VarReader,
+    {
+        let value = match reader.var(var) {
+            Ok(value) => value,
+            Err(e) => return Err(Error::var(var, e)),
+        };
+        // Resolve the environment through the cache, then borrow it mutably.
+        let _ = self.lookup(var, value)?;
+        let cache = self.cache.lock();
+        let Some((value, environment)) = cache.as_ref() else {
+            return Err(Error::var(var, VarError::NotPresent));
+        };
+        match self.envs.get_mut(environment) {
+            Some(config) => Ok(config),
+            None => Err(Error::invalid_current_environment(var, value.trim())),
+        }
+    }
+
+    /// Find the current environment and its config: the environment pinned
+    /// with `select`, or else the one named by the selection variable, read
+    /// with the given reader.  `None` if nothing is pinned and the variable is
+    /// not set.  Every `current_*` accessor resolves the environment here.
+    #[track_caller]
+    fn find_current<R>(&self, reader: &R) -> Result<Option<(&S, &T)>>
+    where
+        R: VarReader,
+    {
+        if let Some(entry) = self.pinned()? {
+            return Ok(Some(entry));
+        }
+
+        let var = self.var();
+        let value = match reader.var(var) {
+            Ok(value) => value,
+            Err(VarError::NotPresent) => return Ok(None),
+            Err(e) => return Err(Error::var(var, e)),
+        };
+        self.lookup(var, value).map(Some)
+    }
+
+    /// Find the current environment as `find_current` does, requiring the
+    /// selection variable to be set if nothing is pinned
+    #[track_caller]
+    fn require_current<R>(&self, reader: &R) -> Result<(&S, &T)>
+    where
+        R: VarReader,
+    {
+        match self.find_current(reader)? {
+            Some(entry) => Ok(entry),
+            None => Err(Error::var(self.var(), VarError::NotPresent)),
+        }
+    }
+
+    /// Convert the value read from the given variable into an environment
+    #[track_caller]
+    fn environment(var: &str, value: &str) -> Result<S> {
+        match S::try_from(value.to_string()) {
+            Ok(environment) => Ok(environment),
+            Err(e) => Err(Error::invalid_current_environment(var, value)
+                .with_source(ErrSource::Environment(e.into()))),
+        }
+    }
+
+    /// Look up the config for the value read from the given variable,
+    /// ignoring any whitespace around it.  The environment converted from the
+    /// value is cached, so reading the same value again skips the conversion.
+    #[track_caller]
+    fn lookup(&self, var: &str, value: String) -> Result<(&S, &T)> {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(var, environment = %value.trim(), "selecting current environment");
+        if let Some(entry) = self.cached(&value) {
+            return Ok(entry);
+        }
+        let environment = Self::environment(var, value.trim())?;
+        self.remember(var, value, environment)
+    }
+}
+
+impl<S, T> Environments<S, T>
+where
+    S: Key + for<'a> TryFrom<&'a str>,
+    for<'a> <S as TryFrom<&'a str>>::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Get the current environment as `current` does, converting the value of
+    /// the selection variable with `TryFrom<&str>` rather than
+    /// `TryFrom<String>`, so no copy of the value is made
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_borrowed(&self) -> Result<&T> {
+        self.current_borrowed_with_reader(&StdVarReader)
+    }
+
+    /// Get the current environment as `current_borrowed` does, reading the
+    /// selection variable with the given `VarReader`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_borrowed_with_reader<R>(&self, reader: &R) -> Result<&T>
+    where
+        R: VarReader,
+    {
+        if let Some((_, config)) = self.pinned()? {
+            return Ok(config);
+        }
+        self.current_from_borrowed_with_reader(self.var(), reader)
+    }
+
+    /// Get the current environment from the given variable as `current_from`
+    /// does, converting its value with `TryFrom<&str>`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from_borrowed(&self, var: &str) -> Result<&T> {
+        self.current_from_borrowed_with_reader(var, &StdVarReader)
+    }
+
+    /// Get the current environment from the given variable as
+    /// `current_from_borrowed` does, reading it with the given `VarReader`
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_from_borrowed_with_reader<R>(&self, var: &str, reader: &R) -> Result<&T>
+    where
+        R: VarReader,
+    {
+        let value = match reader.var(var) {
+            Ok(value) => value,
+            Err(e) => return Err(Error::var(var, e)),
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(var, environment = %value.trim(), "selecting current environment");
+        if let Some((_, config)) = self.cached(&value) {
+            return Ok(config);
+        }
+        let environment = match S::try_from(value.trim()) {
+            Ok(environment) => environment,
+            Err(e) => {
+                return Err(Error::invalid_current_environment(var, value.trim())
+                    .with_source(ErrSource::Environment(e.into())))
+            }
+        };
+        self.remember(var, value, environment)
+            .map(|(_, config)| config)
+    }
+}
+
+impl<S, T> Environments<S, T>
+where
+    S: Key,
+{
+    /// Create an empty set of environments.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the config for the given environment.
+    pub fn get(&self, key: &S) -> Option<&T> {
+        self.envs.get(key)
+    }
+
+    /// Get a mutable reference to the config for the given environment.
+    pub fn get_mut(&mut self, key: &S) -> Option<&mut T> {
+        self.envs.get_mut(key)
+    }
+
+    /// Get the config for the given environment, with any unset values
+    /// inherited from the more restrictive environments above it in the
+    /// hierarchy, nearest first.  For the `Environment` hierarchy, `dev`
+    /// inherits from `test`, which inherits from `stage`, and so on up to
+    /// `prod`.
+    pub fn resolve(&self, key: &S) -> Option<T>
+    where
+        T: Clone + Merge,
+    {
+        let mut chain = self.chain(key)?;
+        let (_, config) = chain.next()?;
+        let mut resolved = config.clone();
+        for (_, parent) in chain {
+            resolved.merge(parent);
+        }
+        Some(resolved)
+    }
+
+    /// Get the first value set for a field, starting at the given environment
+    /// and walking up the hierarchy.  For a `key: Option<String>` field,
+    /// `dev` with no key falls back to the key of `test`, `stage`, then
+    /// `prod`.  This is `resolve` for a single field, without needing
+    /// `Merge`.
+    pub fn resolve_field<'a, V, F>(&'a self, start: &S, extractor: F) -> Option<&'a V>
+    where
+        F: Fn(&'a T) -> &'a Option<V>,
+    {
+        self.chain(start)?
+            .find_map(|(_, config)| extractor(config).as_ref())
+    }
+
+    /// The given environment followed by the more restrictive environments
+    /// above it in the hierarchy, nearest first, or `None` if there is no
+    /// config for the given environment
+    fn chain(&self, key: &S) -> Option<impl Iterator<Item = (&S, &T)>> {
+        #[cfg(not(feature = "indexmap"))]
+        let chain = if self.envs.contains_key(key) {
+            self.envs.range(..=key).rev()
+        } else {
+            return None;
+        };
+        #[cfg(feature = "indexmap")]
+        let chain = {
+            let index = self.envs.get_index_of(key)?;
+            self.envs.iter().take(index + 1).rev()
+        };
+        Some(chain)
+    }
+
+    /// The environment pinned with `select` and its config, if one is pinned
+    #[track_caller]
+    fn pinned(&self) -> Result<Option<(&S, &T)>> {
+        let Some(selected) = &self.selected else {
+            return Ok(None);
+        };
+        match self.envs.get_key_value(selected) {
+            Some(entry) => Ok(Some(entry)),
+            None => Err(Error::invalid_selected_environment()),
+        }
+    }
+
+    /// The environment and config cached for the given value of a selection
+    /// variable, if the value is unchanged since it was cached
+    fn cached(&self, value: &str) -> Option<(&S, &T)> {
+        match self.cache.lock().as_ref() {
+            Some((cached, environment)) if cached == value => self.envs.get_key_value(environment),
+            _ => None,
+        }
+    }
+
+    /// Look up the config for the environment converted from the value read
+    /// from the given variable, and cache the environment for that value
+    #[track_caller]
+    fn remember(&self, var: &str, value: String, environment: S) -> Result<(&S, &T)> {
+        let Some(entry) = self.envs.get_key_value(&environment) else {
+            return Err(Error::invalid_current_environment(var, value.trim()));
+        };
+        *self.cache.lock() = Some((value, environment));
+        Ok(entry)
+    }
+
+    /// Move the given environments to the front, in order, keeping the rest
+    /// in their current order after them
+    #[cfg(feature = "indexmap")]
+    fn reorder<I>(&mut self, order: I)
+    where
+        I: IntoIterator<Item = S>,
+    {
+        let mut ordered = IndexMap::with_capacity(self.envs.len());
+        for key in order {
+            if let Some(config) = self.envs.shift_remove(&key) {
+                let _prev = ordered.insert(key, config);
+            }
+        }
+        ordered.extend(self.envs.drain(..));
+        self.envs = ordered;
+    }
+
+    /// Is there config for the given environment?
+    #[must_use]
+    pub fn contains(&self, key: &S) -> bool {
+        self.envs.contains_key(key)
+    }
+
+    /// Insert the config for the given environment, returning the previous
+    /// config if one was present.
+    pub fn insert(&mut self, key: S, value: T) -> Option<T> {
+        self.envs.insert(key, value)
+    }
+
+    /// Remove the config for the given environment, returning it if it was
+    /// present.
+    pub fn remove(&mut self, key: &S) -> Option<T> {
+        #[cfg(not(feature = "indexmap"))]
+        let removed = self.envs.remove(key);
+        #[cfg(feature = "indexmap")]
+        let removed = self.envs.shift_remove(key);
+        removed
+    }
+
+    /// Keep only the environments for which `f` returns `true`, e.g. to strip
+    /// developer-only environments before deploying.
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&S, &mut T) -> bool,
+    {
+        self.envs.retain(f);
+    }
+
+    /// Iterate over the environments and their config, in hierarchy order.
+    pub fn iter(&self) -> impl Iterator<Item = (&S, &T)> {
+        self.envs.iter()
+    }
+
+    /// Iterate mutably over the environments and their config, in hierarchy
+    /// order.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&S, &mut T)> {
+        self.envs.iter_mut()
+    }
+
+    /// Iterate over the environments, in hierarchy order.
+    pub fn keys(&self) -> impl Iterator<Item = &S> {
+        self.envs.keys()
+    }
+
+    /// Iterate over the environment config, in hierarchy order.
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.envs.values()
+    }
+
+    /// Consume the environments, returning the underlying map.
+    #[must_use]
+    pub fn into_inner(self) -> Map<S, T> {
+        self.envs
+    }
+
+    /// Transform the config of every environment, keeping the environment
+    /// keys.
+    pub fn map_values<U, F>(self, mut f: F) -> Environments<S, U>
+    where
+        F: FnMut(T) -> U,
+    {
+        Environments {
+            envs: self
+                .envs
+                .into_iter()
+                .map(|(environment, config)| (environment, f(config)))
+                .collect(),
+            var: self.var,
+            selected: self.selected,
+            cache: CurrentCache::default(),
+        }
+    }
+
+    /// Transform the config of every environment with a fallible function,
+    /// keeping the environment keys.  Stops at the first error, in hierarchy
+    /// order.
+    ///
+    /// # Errors
+    ///
+    /// The first error is returned along with the environment it came from.
+    pub fn try_map_values<U, E, F>(
+        self,
+        mut f: F,
+    ) -> std::result::Result<Environments<S, U>, (S, E)>
+    where
+        F: FnMut(T) -> std::result::Result<U, E>,
+    {
+        let mut envs = Map::new();
+        for (environment, config) in self.envs {
+            match f(config) {
+                Ok(config) => {
+                    let _prev = envs.insert(environment, config);
+                }
+                Err(e) => return Err((environment, e)),
+            }
+        }
+        Ok(Environments {
+            envs,
+            var: self.var,
+            selected: self.selected,
+            cache: CurrentCache::default(),
+        })
+    }
+
+    /// The environments that have config, in hierarchy order.
+    #[must_use]
+    pub fn available_environments(&self) -> Vec<&S> {
+        self.envs.keys().collect()
+    }
+
+    /// The number of environments.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    /// Are there no environments?
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    /// Replace the config for every environment, keeping the selection
+    /// variable
+    pub(crate) fn replace(&mut self, other: Self) {
+        self.envs = other.envs;
+    }
+
+    /// Use the given variable to select the current environment
+    pub(crate) fn set_var_name(&mut self, var: String) {
+        self.var = Some(var);
+    }
+
+    /// The variable used to select the current environment
+    fn var(&self) -> &str {
+        self.var.as_deref().unwrap_or("env")
+    }
+
+    /// Pin the current environment, so `current` and `current_mut` return it
+    /// without reading the selection variable, e.g. once at startup.
+    pub fn select(&mut self, key: S) {
+        self.selected = Some(key);
+    }
+
+    /// The environment pinned with `select`, if any.
+    #[must_use]
+    pub fn selected(&self) -> Option<&S> {
+        self.selected.as_ref()
+    }
+
+    /// Unpin the current environment, returning it if one was pinned.
+    pub fn unselect(&mut self) -> Option<S> {
+        self.selected.take()
+    }
+
+    /// Forget the environment cached from the last value of the selection
+    /// variable.  The variable is still read on every call to `current`, and
+    /// the cache is only used while its value is unchanged, so this is only
+    /// needed to force the value to be converted again.
+    pub fn invalidate_current_cache(&self) {
+        self.cache.clear();
+    }
+
+    /// Check that there is config for every required environment, e.g.
+    /// `envs.validate(Environment::all())`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn validate<I>(&self, required: I) -> Result<()>
+    where
+        I: IntoIterator<Item = S>,
+        S: fmt::Display,
+    {
+        let missing: Vec<String> = required
+            .into_iter()
+            .filter(|key| !self.envs.contains_key(key))
+            .map(|key| key.to_string())
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::missing_environments(&missing))
+        }
+    }
+}
+
+impl<S, T> Environments<S, T>
+where
+    S: Key + Clone,
+    T: Serialize,
+{
+    /// Compare these (old) environments with `other` (new), listing the
+    /// environments that were added or removed, and the keys that changed in
+    /// each environment present in both.
+    ///
+    /// Each config is serialized to a `toml::Value` to find the changed keys,
+    /// so this fails with a `Parse` error if a config can't be represented in
+    /// TOML, e.g. a unit value or a map with non-string keys.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn diff(&self, other: &Self) -> Result<Vec<EnvDiff<S>>> {
+        let mut diffs = Vec::new();
+        for (environment, old) in &self.envs {
+            match other.envs.get(environment) {
+                Some(new) => {
+                    let keys = diff::changed_keys(
+                        &toml::Value::try_from(old)?,
+                        &toml::Value::try_from(new)?,
+                        "",
+                    );
+                    if !keys.is_empty() {
+                        diffs.push(EnvDiff::Changed {
+                            environment: environment.clone(),
+                            keys,
+                        });
+                    }
+                }
+                None => diffs.push(EnvDiff::Removed(environment.clone())),
+            }
+        }
+        diffs.extend(
+            other
+                .envs
+                .keys()
+                .filter(|environment| !self.envs.contains_key(*environment))
+                .cloned()
+                .map(EnvDiff::Added),
+        );
+        Ok(diffs)
+    }
+}
+
+impl<S, T> Environments<S, T>
+where
+    S: Enumerable + Key,
+    T: Clone + Default,
+{
+    /// Environments with `T::default()` as the config of every environment,
+    /// e.g. to write out a sample config with `to_string`.
+    #[must_use]
+    pub fn example() -> Self {
+        let config = T::default();
+        S::all()
+            .into_iter()
+            .map(|environment| (environment, config.clone()))
+            .collect()
+    }
+}
+
+impl<T> Environments<Environment, T> {
+    /// Get the current environment, falling back to `Dev` in debug builds or
+    /// `Prod` in release builds if the selection variable is not set, so
+    /// `cargo run` needs no `env` variable.  See `current_for_profile_with`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current_for_profile(&self) -> Result<&T> {
+        self.current_for_profile_with(&Environment::Dev, &Environment::Prod)
+    }
+}
+
+impl<T> Environments<Environment, T>
+where
+    T: Clone + Default + Serialize,
+{
+    /// A starter `env.toml`, with a section for every `Environment` holding
+    /// the fields of `T::default()`.  See `example`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn template_string() -> Result<String> {
+        Self::example().to_string()
+    }
+
+    /// Write a starter `env.toml` to a path, creating or truncating the file.
+    /// See `template_string`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn write_template(path: &Path) -> Result<()> {
+        Self::example().to_path(path)
+    }
+}
+
+impl<S, T> Environments<S, T>
+where
+    S: Hierarchy,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    /// Check that the file at a path is a valid environments file, without
+    /// deserializing any config, e.g. to lint `env.toml` in CI.  The file must
+    /// be TOML with an `[envs]` table holding a table for each environment of
+    /// the hierarchy.  The config type is not used, so any `T` will do.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn validate_path(path: &Path) -> Result<()> {
+        let document = document(toml::from_str(&read_path(path)?)?, "envs", true)?;
+        let Some(envs) = document["envs"].as_table() else {
+            return Err(Error::invalid_table("envs"));
+        };
+        for (name, env) in envs {
+            if let Err(e) = S::try_from(name.clone()) {
+                return Err(
+                    Error::invalid_environment(name).with_source(ErrSource::Environment(e.into()))
+                );
+            }
+            if !env.is_table() {
+                return Err(Error::invalid_table(&format!("envs.{name}")));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S, T> Default for Environments<S, T>
+where
+    S: Key,
+{
+    fn default() -> Self {
+        Self::from(Map::new())
+    }
+}
+
+impl<S, T> From<Map<S, T>> for Environments<S, T>
+where
+    S: Key,
+{
+    fn from(envs: Map<S, T>) -> Self {
+        Self {
+            envs,
+            var: None,
+            selected: None,
+            cache: CurrentCache::default(),
+        }
+    }
+}
+
+/// Get the config for an environment that must be present, e.g.
+/// `envs[Environment::Prod]`.
+impl<S, T> Index<S> for Environments<S, T>
+where
+    S: Key + fmt::Debug,
+{
+    type Output = T;
+
+    /// # Panics
+    ///
+    /// Panics if there is no config for the environment.
+    #[track_caller]
+    fn index(&self, key: S) -> &T {
+        match self.envs.get(&key) {
+            Some(config) => config,
+            None => panic!("no config for environment {:?}", key),
+        }
+    }
+}
+
+/// Get the config for an environment that must be present mutably.
+impl<S, T> IndexMut<S> for Environments<S, T>
+where
+    S: Key + fmt::Debug,
+{
+    /// # Panics
+    ///
+    /// Panics if there is no config for the environment.
+    #[track_caller]
+    fn index_mut(&mut self, key: S) -> &mut T {
+        match self.envs.get_mut(&key) {
+            Some(config) => config,
+            None => panic!("no config for environment {:?}", key),
+        }
+    }
+}
+
+impl<S, T> IntoIterator for Environments<S, T>
+where
+    S: Key,
+{
+    type Item = (S, T);
+    type IntoIter = map::IntoIter<S, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.envs.into_iter()
+    }
+}
+
+impl<'a, S, T> IntoIterator for &'a Environments<S, T>
+where
+    S: Key,
+{
+    type Item = (&'a S, &'a T);
+    type IntoIter = map::Iter<'a, S, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.envs.iter()
+    }
+}
+
+impl<'a, S, T> IntoIterator for &'a mut Environments<S, T>
+where
+    S: Key,
+{
+    type Item = (&'a S, &'a mut T);
+    type IntoIter = map::IterMut<'a, S, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.envs.iter_mut()
+    }
+}
+
+impl<S, T> FromIterator<(S, T)> for Environments<S, T>
+where
+    S: Key,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (S, T)>,
+    {
+        Self::from(iter.into_iter().collect::<Map<S, T>>())
+    }
+}
+
+/// The path in the `TOMLENV_PATH` environment variable, or `env.toml`
+pub(crate) fn default_path() -> PathBuf {
+    env::var_os(PATH_VAR).map_or_else(|| PathBuf::from("env.toml"), PathBuf::from)
+}
+
+/// Read the file at the given path to a string
+#[track_caller]
+pub(crate) fn read_path(path: &Path) -> Result<String> {
+    let mut buffer = String::new();
+    let read = File::open(path).and_then(|file| {
+        let len = file.metadata().map_or(0, |metadata| metadata.len());
+        buffer.reserve(usize::try_from(len).unwrap_or(0));
+        BufReader::new(file).read_to_string(&mut buffer)
+    });
+    if let Err(e) = read {
+        return Err(Error::read_path(path, e));
+    }
+    #[cfg(feature = "tracing")]
+    tracing::debug!(path = %path.display(), bytes = buffer.len(), "read environments");
+    Ok(buffer)
+}
+
+/// Read the given selection variable with the given reader, ignoring any
+/// whitespace around its value, e.g. a trailing newline from shell command
+/// substitution
+#[track_caller]
+fn read_var<R>(var: &str, reader: &R) -> Result<String>
+where
+    R: VarReader,
+{
+    match reader.var(var) {
+        Ok(value) => Ok(value.trim().to_owned()),
+        Err(e) => Err(Error::var(var, e)),
+    }
+}
+
+/// Prepare a TOML document, moving the given top-level table to `envs`,
+/// resolving any `inherits` keys, and optionally merging any `defaults` table
+/// under each environment.  The `envs` table must not be missing or empty.
+#[track_caller]
+pub(crate) fn document(
+    mut document: toml::Value,
+    table_name: &str,
+    defaults: bool,
+) -> Result<toml::Value> {
+    value::rename_table(&mut document, table_name, "envs");
+    match document.get("envs") {
+        None => return Err(Error::no_environments()),
+        Some(toml::Value::Table(envs)) if envs.is_empty() => return Err(Error::no_environments()),
+        Some(_) => {}
+    }
+    value::apply_inherits(&mut document)?;
+    if defaults {
+        value::apply_defaults(&mut document)?;
+    }
+    Ok(document)
+}
+
+/// Deserialize the map of environments, rejecting two names that convert to
+/// the same environment, e.g. `prod` and `production`, rather than letting
+/// the later one silently replace the earlier
+fn unique_envs<'de, D, S, T>(deserializer: D) -> std::result::Result<Map<S, T>, D::Error>
+where
+    D: Deserializer<'de>,
+    S: Key + Deserialize<'de>,
+    T: Deserialize<'de>,
+{
+    struct UniqueEnvs<S, T>(PhantomData<(S, T)>);
+
+    impl<'de, S, T> Visitor<'de> for UniqueEnvs<S, T>
+    where
+        S: Key + Deserialize<'de>,
+        T: Deserialize<'de>,
+    {
+        type Value = Map<S, T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+            formatter.write_str("a table of environments")
+        }
+
+        fn visit_map<A>(self, mut map: A) -> std::result::Result<Map<S, T>, A::Error>
+        where
+            A: MapAccess<'de>,
+        {
+            let mut envs: Map<S, (String, T)> = Map::new();
+            while let Some(name) = map.next_key::<String>()? {
+                let environment = S::deserialize(name.clone().into_deserializer())?;
+                let config = map.next_value()?;
+                match envs.entry(environment) {
+                    map::Entry::Occupied(entry) => {
+                        let (first, _) = entry.get();
+                        return Err(de::Error::custom(format!(
+                            "'{first}' and '{name}' are the same environment"
+                        )));
+                    }
+                    map::Entry::Vacant(entry) => {
+                        let _ = entry.insert((name, config));
+                    }
+                }
+            }
+            Ok(envs
+                .into_iter()
+                .map(|(environment, (_, config))| (environment, config))
+                .collect())
+        }
+    }
+
+    deserializer.deserialize_map(UniqueEnvs(PhantomData))
+}
+
+impl<S, T> FromStr for Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    type Err = Error;
+
+    fn from_str(toml: &str) -> Result<Self> {
+        Environments::from_str(toml)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<S, T> Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Load the environments from a path without blocking the async runtime.
+    ///
+    /// # Errors
+    ///
+    pub async fn from_path_async(path: &Path) -> Result<Self> {
+        let toml = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| Error::read_path(path, e))?;
+        Self::from_str(&toml)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<S, T> Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Load the environments from a gzip-compressed TOML file, e.g.
+    /// `env.toml.gz`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_gz_path(path: &Path) -> Result<Self> {
+        let mut toml = String::new();
+        let read = File::open(path)
+            .and_then(|file| GzDecoder::new(BufReader::new(file)).read_to_string(&mut toml));
+        if let Err(e) = read {
+            return Err(Error::read_path(path, e));
+        }
+        Self::from_str(&toml)
+    }
+}
+
+#[cfg(feature = "http")]
+impl<S, T> Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Load the environments from the TOML body of an HTTP(S) GET request.
+    /// The request times out after 30 seconds.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_url(url: &str) -> Result<Self> {
+        let response = ureq::AgentBuilder::new()
+            .timeout(HTTP_TIMEOUT)
+            .build()
+            .get(url)
+            .call();
+        let toml = match response {
+            Ok(response) => response.into_string(),
+            Err(e) => return Err(Error::fetch_url(url, e)),
+        };
+        let toml = match toml {
+            Ok(toml) => toml,
+            Err(e) => return Err(Error::read_url(url, e)),
+        };
+        Self::from_str(&toml)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<S, T> Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Load the environments from a JSON string.  The document is read as
+    /// TOML would be, with `defaults` and `inherits` applied.  A `null` is
+    /// read as a missing value, as TOML has none.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_json_str(json: &str) -> Result<Self> {
+        let document = serde_json::from_str::<Nullable>(json)?.into_document();
+        Ok(Self::parse(document, "envs", true)?
+            .in_declared_order(&mut serde_json::Deserializer::from_str(json), "envs"))
+    }
+
+    /// Load the environments from a reader supplying JSON
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_json_reader<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let mut buffer = String::new();
+        let _ = reader.read_to_string(&mut buffer)?;
+        Self::from_json_str(&buffer)
+    }
+
+    /// Serialize the environments to a JSON string
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn to_json_string(&self) -> Result<String>
+    where
+        T: Serialize,
+        S: Serialize,
+    {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl<S, T> Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Load the environments from a YAML string.  The document is read as
+    /// TOML would be, with `defaults` and `inherits` applied.  A `null` is
+    /// read as a missing value, as TOML has none.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_yaml_str(yaml: &str) -> Result<Self> {
+        let document = serde_yaml::from_str::<Nullable>(yaml)?.into_document();
+        Ok(Self::parse(document, "envs", true)?
+            .in_declared_order(serde_yaml::Deserializer::from_str(yaml), "envs"))
+    }
+
+    /// Load the environments from a reader supplying YAML
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_yaml_reader<R>(mut reader: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let mut buffer = String::new();
+        let _ = reader.read_to_string(&mut buffer)?;
+        Self::from_yaml_str(&buffer)
+    }
+
+    /// Serialize the environments to a YAML string
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn to_yaml_string(&self) -> Result<String>
+    where
+        T: Serialize,
+        S: Serialize,
+    {
+        Ok(serde_yaml::to_string(self)?)
+    }
+}
+
+#[cfg(feature = "clap")]
+impl<S, T> Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Load the environments from the file named `file_name` in the
+    /// directory given by the `arg_name` argument, or in the current
+    /// directory if the argument is not present.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn from_matches(matches: &ArgMatches<'_>, arg_name: &str, file_name: &str) -> Result<Self> {
+        let env_path = if let Some(env_path) = matches.value_of(arg_name) {
+            PathBuf::from(env_path).join(file_name)
+        } else {
+            PathBuf::from(file_name)
+        };
+
+        Environments::from_path(env_path.as_path())
+    }
+}
+
+#[cfg(feature = "clap")]
+impl<'a, S, T> TryFrom<&'a ArgMatches<'a>> for Environments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    type Error = Error;
+
+    fn try_from(matches: &'a ArgMatches<'a>) -> Result<Self> {
+        Environments::from_matches(matches, "env_path", "env.toml")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Environments, Map};
+    #[cfg(any(feature = "gzip", feature = "http"))]
+    use crate::error::ErrCode;
+    use crate::{
+        env::{EnvDiff, Environment, Merge, StringEnv},
+        error::{Error, Result},
+    };
+    #[cfg(feature = "clap")]
+    use clap::{App, Arg};
+    use getset::Getters;
+    use serde::{Deserialize, Serialize};
+    use std::convert::TryFrom;
+    use std::{
+        collections::HashMap,
+        env,
+        ffi::{OsStr, OsString},
+        fs::remove_file,
+        io::Cursor,
+        sync::{Mutex, PoisonError},
+    };
+    #[cfg(feature = "clap")]
+    use std::{
+        fs::OpenOptions,
+        io::{BufWriter, Write},
+    };
+
+    const TOMLENV: &str = "TOMLENV";
+    // Serializes the tests that set the process-wide `env` variable.
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+    const EXPECTED_TOML_STR: &str = r#"[envs.prod]
+name = "Production"
+key = "abcd-123-efg-45"
+
+[envs.stage]
+name = "Stage"
+
+[envs.test]
+name = "Test"
+
+[envs.dev]
+name = "Development"
+
+[envs.local]
+name = "Local"
+"#;
+
+    #[derive(Clone, Debug, Deserialize, Getters, Serialize)]
+    struct RuntimeEnv {
+        #[get]
+        name: String,
+        #[get]
+        key: Option<String>,
+    }
+
+    impl Merge for RuntimeEnv {
+        fn merge(&mut self, parent: &Self) {
+            self.key.merge(&parent.key);
+        }
+    }
+
+    /// Sets an environment variable for a test, restoring its previous value
+    /// when dropped, even if the test panics
+    struct EnvVar {
+        name: String,
+        previous: Option<OsString>,
+    }
+
+    impl EnvVar {
+        fn new(name: &str) -> Self {
+            Self {
+                name: name.to_string(),
+                previous: env::var_os(name),
+            }
+        }
+
+        fn set<V>(&self, value: V)
+        where
+            V: AsRef<OsStr>,
+        {
+            env::set_var(&self.name, value);
+        }
+
+        fn remove(&self) {
+            env::remove_var(&self.name);
+        }
+    }
+
+    impl Drop for EnvVar {
+        fn drop(&mut self) {
+            match &self.previous {
+                Some(value) => env::set_var(&self.name, value),
+                None => env::remove_var(&self.name),
+            }
+        }
+    }
+
+    fn fixture() -> Environments<Environment, RuntimeEnv> {
+        try_decode(EXPECTED_TOML_STR).expect("Unable to decode TOML to Environments!")
+    }
+
+    fn try_decode(toml: &str) -> Result<Environments<Environment, RuntimeEnv>> {
+        let mut cursor = Cursor::new(toml);
+        Ok(Environments::from_reader(&mut cursor)?)
+    }
+
+    fn try_encode(environments: &Environments<Environment, RuntimeEnv>) -> Result<String> {
+        environments.to_string()
+    }
+
+    fn try_current(envs: &Environments<Environment, RuntimeEnv>, expected: &str) -> Result<()> {
+        let current = envs.current()?;
+        assert_eq!(current.name(), expected);
+        Ok(())
+    }
+
+    fn try_current_from(
+        var: &str,
+        envs: &Environments<Environment, RuntimeEnv>,
+        expected: &str,
+    ) -> Result<()> {
+        let current = envs.current_from(var)?;
+        assert_eq!(current.name(), expected);
+        Ok(())
+    }
+
+    #[cfg(feature = "clap")]
+    fn test_cli() -> App<'static, 'static> {
+        App::new("env-from-app-matches")
+            .version("1")
+            .author("Yoda")
+            .about("command line for proxy config testing")
+            .arg(
+                Arg::with_name("env_path")
+                    .short("e")
+                    .long("envpath")
+                    .takes_value(true)
+                    .value_name("ENV_PATH"),
+            )
+    }
+
+    #[test]
+    fn decode() {
+        match try_decode(EXPECTED_TOML_STR) {
+            Ok(_) => assert!(true, "Successfully decode TOML to Environments"),
+            Err(_) => assert!(false, "Unable to decode TOML to Environments!"),
+        }
+    }
+
+    #[test]
+    fn decode_defaults() {
+        let toml = r#"[defaults]
+name = "Unnamed"
+key = "default-key"
+
+[envs.prod]
+name = "Production"
+key = "abcd-123-efg-45"
+
+[envs.dev]
+"#;
+        let envs = try_decode(toml).expect("Unable to decode TOML to Environments!");
+        let prod = envs
+            .get(&Environment::Prod)
+            .expect("Prod should be present");
+        assert_eq!(prod.name(), "Production");
+        assert_eq!(prod.key().as_deref(), Some("abcd-123-efg-45"));
+        let dev = envs.get(&Environment::Dev).expect("Dev should be present");
+        assert_eq!(dev.name(), "Unnamed");
+        assert_eq!(dev.key().as_deref(), Some("default-key"));
+        assert_eq!(envs.len(), 2);
+    }
+
+    #[test]
+    fn from_str() {
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_str(EXPECTED_TOML_STR).expect("Unable to parse Environments!");
+        assert_eq!(envs.len(), 5);
+        let parsed: Environments<Environment, RuntimeEnv> = EXPECTED_TOML_STR
+            .parse()
+            .expect("Unable to parse Environments!");
+        assert_eq!(
+            try_encode(&parsed).expect("Unable to encode Environments to TOML"),
+            EXPECTED_TOML_STR
+        );
+        assert!("[envs.blah]\nname = \"Blah\"\n"
+            .parse::<Environments<Environment, RuntimeEnv>>()
+            .is_err());
+    }
+
+    #[test]
+    fn load() {
+        let var = EnvVar::new("TOMLENV_PATH");
+        let path = env::temp_dir().join("tomlenv-load.toml");
+        std::fs::write(&path, EXPECTED_TOML_STR).expect("Unable to write TOML");
+        var.set(&path);
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::load().expect("Unable to load TOMLENV_PATH");
+        assert_eq!(envs.len(), 5);
+        remove_file(&path).expect("Unable to remove TOML");
+
+        let missing: Result<Environments<Environment, RuntimeEnv>> = Environments::load();
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn from_path_ancestors() {
+        let root = env::temp_dir().join("tomlenv-ancestors");
+        let nested = root.join("a").join("b");
+        std::fs::create_dir_all(&nested).expect("Unable to create nested dirs");
+        std::fs::write(root.join("tomlenv-ancestors.toml"), EXPECTED_TOML_STR)
+            .expect("Unable to write TOML");
+
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_path_ancestors(&nested, "tomlenv-ancestors.toml")
+                .expect("Unable to find TOML in an ancestor");
+        assert_eq!(envs.len(), 5);
+
+        let missing: Result<Environments<Environment, RuntimeEnv>> =
+            Environments::from_path_ancestors(&nested, "tomlenv-ancestors-missing.toml");
+        let err = missing.expect_err("No ancestor has the file");
+        assert!(err.reason().contains(&format!("'{}'", nested.display())));
+        assert!(err.reason().contains(&format!("'{}'", root.display())));
+
+        std::fs::remove_dir_all(&root).expect("Unable to remove nested dirs");
+    }
+
+    #[test]
+    fn diff() {
+        let old = fixture();
+        let mut new = old.clone();
+        let _prev = new.remove(&Environment::Local);
+        if let Some(dev) = new.get_mut(&Environment::Dev) {
+            dev.key = Some("dev-key".to_string());
+        }
+        if let Some(prod) = new.get_mut(&Environment::Prod) {
+            prod.name = "Prod".to_string();
+        }
+
+        assert_eq!(
+            old.diff(&new).ok(),
+            Some(vec![
+                EnvDiff::Changed {
+                    environment: Environment::Prod,
+                    keys: vec!["name".to_string()],
+                },
+                EnvDiff::Changed {
+                    environment: Environment::Dev,
+                    keys: vec!["key".to_string()],
+                },
+                EnvDiff::Removed(Environment::Local),
+            ])
+        );
+        assert_eq!(
+            new.diff(&old).ok().and_then(|diffs| diffs.last().cloned()),
+            Some(EnvDiff::Added(Environment::Local))
+        );
+
+        let unit: Environments<Environment, ()> =
+            vec![(Environment::Prod, ())].into_iter().collect();
+        let err = unit
+            .diff(&unit)
+            .expect_err("() can't be represented in TOML");
+        assert_eq!(err.reason(), "There was an error serializing TOML");
+    }
+
+    #[test]
+    fn from_slice() {
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_slice(EXPECTED_TOML_STR.as_bytes())
+                .expect("Unable to parse Environments!");
+        assert_eq!(envs.len(), 5);
+
+        let invalid: Result<Environments<Environment, RuntimeEnv>> =
+            Environments::from_slice(&[0x5b, 0xff, 0xfe, 0x5d]);
+        match invalid {
+            Ok(_) => panic!("Invalid UTF-8 should not parse"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "parse: There was an error decoding UTF-8: invalid utf-8 sequence of 1 bytes from index 1"
+            ),
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let mut envs = Map::new();
+        let prod = RuntimeEnv {
+            name: "Production".to_string(),
+            key: Some("abcd-123-efg-45".to_string()),
+        };
+        let stage = RuntimeEnv {
+            name: "Stage".to_string(),
+            key: None,
+        };
+        let test = RuntimeEnv {
+            name: "Test".to_string(),
+            key: None,
+        };
+        let dev = RuntimeEnv {
+            name: "Development".to_string(),
+            key: None,
+        };
+        let local = RuntimeEnv {
+            name: "Local".to_string(),
+            key: None,
+        };
+        let _b = envs.insert(Environment::Prod, prod);
+        let _b = envs.insert(Environment::Stage, stage);
+        let _b = envs.insert(Environment::Test, test);
+        let _b = envs.insert(Environment::Dev, dev);
+        let _b = envs.insert(Environment::Local, local);
+
+        let environments = Environments::from(envs);
+
+        match try_encode(&environments) {
+            Ok(toml) => assert_eq!(toml, EXPECTED_TOML_STR, "TOML strings match"),
+            Err(_) => assert!(false, "Unable to encode Environments to TOML"),
+        }
+    }
+
+    #[test]
+    fn to_path() {
+        let env_toml = env::temp_dir().join("tomlenv-to-path.toml");
+        let envs = fixture();
+        envs.to_path(&env_toml)
+            .expect("Unable to write Environments to path");
+        let written: Environments<Environment, RuntimeEnv> =
+            Environments::from_path(&env_toml).expect("Unable to read Environments from path");
+        assert_eq!(
+            try_encode(&written).expect("Unable to encode Environments to TOML"),
+            EXPECTED_TOML_STR
+        );
+        remove_file(env_toml).expect("Unable to remove tmp 'tomlenv-to-path.toml'");
+    }
+
+    #[test]
+    fn from_paths() {
+        let base = env::temp_dir().join("tomlenv-base.toml");
+        let local = env::temp_dir().join("tomlenv-local.toml");
+        let missing = env::temp_dir().join("tomlenv-missing.toml");
+        try_decode(EXPECTED_TOML_STR)
+            .and_then(|envs| envs.to_path(&base))
+            .expect("Unable to write base");
+        try_decode("[envs.local]\nname = \"My Local\"\nkey = \"local-key\"\n")
+            .and_then(|envs| envs.to_path(&local))
+            .expect("Unable to write local");
+
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_paths(&[&base, &local]).expect("Unable to load paths");
+        assert_eq!(envs.len(), 5);
+        let merged = envs
+            .get(&Environment::Local)
+            .expect("Local should be present");
+        assert_eq!(merged.name(), "My Local");
+        assert_eq!(merged.key().as_deref(), Some("local-key"));
+        assert_eq!(
+            envs.get(&Environment::Prod).map(RuntimeEnv::name),
+            Some(&"Production".to_string())
+        );
+
+        let result: Result<Environments<Environment, RuntimeEnv>> =
+            Environments::from_paths(&[&base, &missing]);
+        match result {
+            Ok(_) => panic!("A missing file should not load"),
+            Err(e) => assert!(e.to_string().contains(&format!("{}", missing.display()))),
+        }
+
+        remove_file(base).expect("Unable to remove tmp 'tomlenv-base.toml'");
+        remove_file(local).expect("Unable to remove tmp 'tomlenv-local.toml'");
+    }
+
+    #[test]
+    fn to_writer() {
+        let envs = fixture();
+        let mut cursor = Cursor::new(Vec::new());
+        envs.to_writer(&mut cursor)
+            .expect("Unable to write Environments to writer");
+        assert_eq!(cursor.into_inner(), EXPECTED_TOML_STR.as_bytes());
+    }
+
+    #[test]
+    fn new() {
+        let mut envs: Environments<Environment, RuntimeEnv> = Environments::new();
+        assert!(envs.is_empty());
+        let _prev = envs.insert(
+            Environment::Local,
+            RuntimeEnv {
+                name: "Local".to_string(),
+                key: None,
+            },
+        );
+        assert_eq!(
+            try_encode(&envs).expect("Unable to encode Environments to TOML"),
+            "[envs.local]\nname = \"Local\"\n"
+        );
+        assert!(Environments::<Environment, RuntimeEnv>::default().is_empty());
+    }
+
+    #[test]
+    fn get() {
+        let envs = fixture();
+        assert_eq!(
+            envs.get(&Environment::Prod).map(RuntimeEnv::name),
+            Some(&"Production".to_string())
+        );
+        assert_eq!(
+            envs.get(&Environment::Prod)
+                .and_then(|e| e.key().as_deref()),
+            Some("abcd-123-efg-45")
+        );
+        assert_eq!(
+            envs.get(&Environment::Local).map(RuntimeEnv::name),
+            Some(&"Local".to_string())
+        );
+    }
+
+    #[test]
+    fn get_mut() {
+        let mut envs = fixture();
+        if let Some(dev) = envs.get_mut(&Environment::Dev) {
+            dev.key = Some("dev-key".to_string());
+        }
+        assert_eq!(
+            envs.get(&Environment::Dev).and_then(|e| e.key().as_deref()),
+            Some("dev-key")
+        );
+    }
+
+    #[test]
+    fn index() {
+        let mut envs = fixture();
+        assert_eq!(envs[Environment::Prod].name(), "Production");
+        envs[Environment::Dev].key = Some("dev-key".to_string());
+        assert_eq!(
+            envs.get(&Environment::Dev).and_then(|e| e.key().as_deref()),
+            Some("dev-key")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no config for environment Local")]
+    fn index_missing() {
+        let mut envs = fixture();
+        let _local = envs.remove(&Environment::Local);
+        assert_eq!(envs[Environment::Local].name(), "Local");
+    }
+
+    #[test]
+    fn retain() {
+        let mut envs = fixture();
+        envs.retain(|environment, _| *environment <= Environment::Test);
+        assert_eq!(
+            envs.keys().copied().collect::<Vec<_>>(),
+            vec![Environment::Prod, Environment::Stage, Environment::Test]
+        );
+        envs.retain(|_, config| config.key().is_some());
+        assert_eq!(envs.len(), 1);
+        assert!(envs.contains(&Environment::Prod));
+    }
+
+    #[test]
+    fn contains() {
+        let mut envs = fixture();
+        assert!(envs.contains(&Environment::Stage));
+        let _stage = envs.remove(&Environment::Stage);
+        assert!(!envs.contains(&Environment::Stage));
+    }
+
+    #[test]
+    fn insert_remove() {
+        let mut envs = fixture();
+        let local = envs
+            .remove(&Environment::Local)
+            .expect("Local should be present");
+        assert_eq!(local.name(), "Local");
+        assert!(envs.get(&Environment::Local).is_none());
+        assert!(envs.remove(&Environment::Local).is_none());
+
+        let replaced = RuntimeEnv {
+            name: "Local Override".to_string(),
+            key: None,
+        };
+        assert!(envs.insert(Environment::Local, local).is_none());
+        let previous = envs
+            .insert(Environment::Local, replaced)
+            .expect("Local should be replaced");
+        assert_eq!(previous.name(), "Local");
+        assert_eq!(
+            envs.get(&Environment::Local).map(RuntimeEnv::name),
+            Some(&"Local Override".to_string())
+        );
+    }
+
+    #[test]
+    fn iter() {
+        let mut envs = fixture();
+        let names: Vec<(Environment, &str)> = envs
+            .iter()
+            .map(|(env, runtime)| (*env, runtime.name().as_str()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                (Environment::Prod, "Production"),
+                (Environment::Stage, "Stage"),
+                (Environment::Test, "Test"),
+                (Environment::Dev, "Development"),
+                (Environment::Local, "Local"),
+            ]
+        );
+
+        for (env, runtime) in envs.iter_mut() {
+            runtime.name = env.to_string();
+        }
+        assert!(envs
+            .iter()
+            .all(|(env, runtime)| *runtime.name() == env.to_string()));
+    }
+
+    #[test]
+    fn into_iter_from_iter() {
+        let envs = fixture();
+        let mut count = 0;
+        for (_env, runtime) in &envs {
+            assert!(!runtime.name().is_empty());
+            count += 1;
+        }
+        assert_eq!(count, 5);
+
+        let collected: Environments<Environment, RuntimeEnv> = envs
+            .into_iter()
+            .filter(|(env, _)| *env != Environment::Prod)
+            .collect();
+        assert_eq!(collected.len(), 4);
+        assert!(!collected.contains(&Environment::Prod));
+        assert_eq!(
+            collected.get(&Environment::Local).map(RuntimeEnv::name),
+            Some(&"Local".to_string())
+        );
+    }
+
+    #[test]
+    fn map_values() {
+        let envs = fixture();
+        let names = envs.map_values(|config| config.name().len());
+        assert_eq!(names.len(), 5);
+        assert_eq!(names.get(&Environment::Prod), Some(&"Production".len()));
+        assert_eq!(names.get(&Environment::Local), Some(&"Local".len()));
+    }
+
+    #[test]
+    fn try_map_values() {
+        let envs = fixture();
+        let keys = envs
+            .clone()
+            .try_map_values(|config| config.key().clone().ok_or("no key"));
+        assert_eq!(keys.err(), Some((Environment::Stage, "no key")));
+
+        let names = envs.try_map_values(|config| Ok::<_, String>(config.name().to_uppercase()));
+        assert_eq!(
+            names
+                .ok()
+                .and_then(|names| names.get(&Environment::Prod).cloned()),
+            Some("PRODUCTION".to_string())
+        );
+    }
+
+    #[test]
+    fn deserialize_only() {
+        #[derive(Debug, Deserialize)]
+        struct LoadOnly {
+            name: String,
+        }
+
+        let envs: Environments<Environment, LoadOnly> =
+            Environments::from_str(EXPECTED_TOML_STR).expect("Unable to decode TOML");
+        assert_eq!(
+            envs.get(&Environment::Dev).map(|env| &env.name[..]),
+            Some("Development")
+        );
+    }
+
+    #[test]
+    fn into_inner() {
+        let envs = fixture();
+        let map = envs.into_inner();
+        assert_eq!(map.len(), 5);
+        let envs = Environments::from(map);
+        assert_eq!(
+            try_encode(&envs).expect("Unable to encode Environments to TOML"),
+            EXPECTED_TOML_STR
+        );
+    }
+
+    #[test]
+    fn keys_values() {
+        let envs = fixture();
+        assert_eq!(
+            envs.keys().copied().collect::<Vec<Environment>>(),
+            vec![
+                Environment::Prod,
+                Environment::Stage,
+                Environment::Test,
+                Environment::Dev,
+                Environment::Local,
+            ]
+        );
+        assert_eq!(
+            envs.values()
+                .map(|e| e.name().as_str())
+                .collect::<Vec<&str>>(),
+            vec!["Production", "Stage", "Test", "Development", "Local"]
+        );
+    }
+
+    #[test]
+    fn available_environments() {
+        let mut envs = fixture();
+        let _test = envs.remove(&Environment::Test);
+        assert_eq!(
+            envs.available_environments(),
+            vec![
+                &Environment::Prod,
+                &Environment::Stage,
+                &Environment::Dev,
+                &Environment::Local,
+            ]
+        );
+    }
+
+    #[test]
+    fn len() {
+        let envs = fixture();
+        assert_eq!(envs.len(), 5);
+        assert!(!envs.is_empty());
+
+        let empty: Environments<Environment, RuntimeEnv> = Environments::new();
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn no_environments() {
+        for toml in ["", "[envs]\n", "[env.prod]\nname = \"Production\"\n"] {
+            let err = try_decode(toml).expect_err("No environments are defined");
+            assert_eq!(err.reason(), "no environments defined");
+        }
+    }
+
+    #[test]
+    fn reload_from_path() {
+        let env_toml = env::temp_dir().join("tomlenv-reload.toml");
+        let mut envs = fixture();
+        envs.set_var_name("TOMLENV_RELOAD".to_string());
+
+        std::fs::write(&env_toml, "[envs.prod\nname = ").expect("Unable to write bad TOML");
+        assert!(envs.reload_from_path(&env_toml).is_err());
+        assert_eq!(envs.len(), 5);
+
+        std::fs::write(&env_toml, "[envs.local]\nname = \"My Local\"\n")
+            .expect("Unable to write TOML");
+        envs.reload_from_path(&env_toml)
+            .expect("Unable to reload environments");
+        assert_eq!(envs.len(), 1);
+        assert_eq!(
+            envs.get(&Environment::Local).map(RuntimeEnv::name),
+            Some(&"My Local".to_string())
+        );
+        assert_eq!(envs.var(), "TOMLENV_RELOAD");
+        remove_file(env_toml).expect("Unable to remove tmp 'tomlenv-reload.toml'");
+    }
+
+    #[test]
+    fn duplicate_environments() {
+        let err = try_decode(
+            "[envs.prod]\nname = \"Prod\"\n\n[envs.production]\nname = \"Production\"\n",
+        )
+        .expect_err("prod and production are the same environment");
+        assert!(err
+            .to_string()
+            .contains("'prod' and 'production' are the same environment"));
+
+        let err = try_decode("[envs.prod]\nname = \"Prod\"\n\n[envs.PROD]\nname = \"Loud\"\n")
+            .expect_err("prod and PROD are the same environment");
+        assert!(err.to_string().contains("are the same environment"));
+
+        let envs = Environments::<Environment, toml::Value>::from_str(
+            "[envs.prod]\nname = \"Prod\"\n\n[envs.stage]\nname = \"Stage\"\n",
+        )
+        .expect("Unable to decode distinct environments");
+        assert_eq!(envs.len(), 2);
+    }
+
+    #[test]
+    fn validate_path() {
+        let env_toml = env::temp_dir().join("tomlenv-validate.toml");
+        let validate = |toml: &str| {
+            std::fs::write(&env_toml, toml).expect("Unable to write TOML");
+            Environments::<Environment, ()>::validate_path(&env_toml)
+                .map_err(|e| e.reason().clone())
+        };
+
+        assert_eq!(validate(EXPECTED_TOML_STR), Ok(()));
+        assert_eq!(
+            validate("[other]\nname = \"Other\"\n"),
+            Err("no environments defined".to_string())
+        );
+        assert_eq!(
+            validate("envs = 1\n"),
+            Err("'envs' must be a table".to_string())
+        );
+        assert_eq!(
+            validate("[envs]\nprod = \"Production\"\n"),
+            Err("'envs.prod' must be a table".to_string())
+        );
+        assert_eq!(
+            validate("[envs.foo]\nname = \"Foo\"\n"),
+            Err("invalid environment 'foo'".to_string())
+        );
+        assert!(validate("[envs.prod\n").is_err());
+        remove_file(env_toml).expect("Unable to remove tmp 'tomlenv-validate.toml'");
+    }
+
+    #[test]
+    fn from_path_missing() {
+        let env_toml = env::temp_dir().join("tomlenv-missing").join("env.toml");
+        let result: Result<Environments<Environment, RuntimeEnv>> =
+            Environments::from_path(&env_toml);
+        match result {
+            Ok(_) => panic!("A missing file should not load"),
+            Err(e) => {
+                assert!(e.to_string().contains(&format!("{}", env_toml.display())));
+                assert!(e.source().is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn resolve() {
+        let mut envs = fixture();
+        let dev = envs.resolve(&Environment::Dev).expect("Dev should resolve");
+        assert_eq!(dev.name(), "Development");
+        assert_eq!(dev.key().as_deref(), Some("abcd-123-efg-45"));
+
+        if let Some(test) = envs.get_mut(&Environment::Test) {
+            test.key = Some("test-key".to_string());
+        }
+        let local = envs
+            .resolve(&Environment::Local)
+            .expect("Local should resolve");
+        assert_eq!(local.key().as_deref(), Some("test-key"));
+        let stage = envs
+            .resolve(&Environment::Stage)
+            .expect("Stage should resolve");
+        assert_eq!(stage.key().as_deref(), Some("abcd-123-efg-45"));
+
+        let _dev = envs.remove(&Environment::Dev);
+        assert!(envs.resolve(&Environment::Dev).is_none());
+    }
+
+    #[test]
+    fn current_chain() {
+        let var = EnvVar::new("TOMLENV_CHAIN");
+        let mut envs = fixture();
+        envs.set_var_name("TOMLENV_CHAIN".to_string());
+        let _stage = envs.remove(&Environment::Stage);
+        var.set("dev");
+        let chain = envs
+            .current_chain()
+            .expect("Unable to get the current chain");
+        assert_eq!(
+            chain
+                .iter()
+                .map(|(environment, config)| (**environment, config.name().as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Environment::Dev, "Development"),
+                (Environment::Test, "Test"),
+                (Environment::Prod, "Production"),
+            ]
+        );
+
+        var.set("prod");
+        assert_eq!(envs.current_chain().map(|chain| chain.len()).ok(), Some(1));
+        var.set("stage");
+        assert!(envs.current_chain().is_err());
+    }
+
+    #[test]
+    fn resolve_field() {
+        let mut envs = fixture();
+        assert_eq!(
+            envs.resolve_field(&Environment::Dev, |config| &config.key)
+                .map(String::as_str),
+            Some("abcd-123-efg-45")
+        );
+
+        if let Some(test) = envs.get_mut(&Environment::Test) {
+            test.key = Some("test-key".to_string());
+        }
+        assert_eq!(
+            envs.resolve_field(&Environment::Local, |config| &config.key)
+                .map(String::as_str),
+            Some("test-key")
+        );
+
+        if let Some(prod) = envs.get_mut(&Environment::Prod) {
+            prod.key = None;
+        }
+        assert!(envs
+            .resolve_field(&Environment::Stage, |config| &config.key)
+            .is_none());
+        let _dev = envs.remove(&Environment::Dev);
+        assert!(envs
+            .resolve_field(&Environment::Dev, |config| &config.key)
+            .is_none());
+    }
+
+    #[test]
+    fn current_merged() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let var = EnvVar::new("env");
+        let envs = fixture();
+        var.set("local");
+        let local = envs.current_merged().expect("Current is not Local!");
+        assert_eq!(local.name(), "Local");
+        assert_eq!(local.key().as_deref(), Some("abcd-123-efg-45"));
+    }
+
+    #[test]
+    fn with_env_overrides() {
+        let override_prod_key = EnvVar::new("TOMLENV_OVERRIDE_PROD_KEY");
+        let override_dev_key = EnvVar::new("TOMLENV_OVERRIDE_DEV_KEY");
+        let envs = fixture();
+        override_prod_key.set("overridden");
+        override_dev_key.set("dev-key");
+        let envs = envs
+            .with_env_overrides("TOMLENV_OVERRIDE")
+            .expect("Unable to apply overrides");
+        assert_eq!(
+            envs.get(&Environment::Prod)
+                .and_then(|e| e.key().as_deref()),
+            Some("overridden")
+        );
+        assert_eq!(
+            envs.get(&Environment::Dev).and_then(|e| e.key().as_deref()),
+            Some("dev-key")
+        );
+        assert_eq!(
+            envs.get(&Environment::Stage)
+                .and_then(|e| e.key().as_deref()),
+            None
+        );
     }
 
     #[test]
     fn current() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let var = EnvVar::new("env");
         match try_decode(EXPECTED_TOML_STR) {
             Ok(ref envs) => {
-                env::set_var("env", "prod");
+                var.set("prod");
                 match try_current(envs, "Production") {
                     Ok(_) => assert!(true, "Found Production Env"),
                     Err(_) => assert!(false, "Current is not Production!"),
                 }
-                env::set_var("env", "stage");
+                var.set("stage");
                 match try_current(envs, "Stage") {
                     Ok(_) => assert!(true, "Found Stage Env"),
                     Err(_) => assert!(false, "Current is not Stage!"),
                 }
-                env::set_var("env", "test");
+                var.set("test");
                 match try_current(envs, "Test") {
                     Ok(_) => assert!(true, "Found Test Env"),
                     Err(_) => assert!(false, "Current is not Test!"),
                 }
-                env::set_var("env", "dev");
+                var.set("dev");
                 match try_current(envs, "Development") {
                     Ok(_) => assert!(true, "Found Development Env"),
                     Err(_) => assert!(false, "Current is not Development!"),
                 }
-                env::set_var("env", "local");
+                var.set("local");
                 match try_current(envs, "Local") {
                     Ok(_) => assert!(true, "Found Local Env"),
                     Err(_) => assert!(false, "Current is not Local!"),
@@ -337,31 +2695,497 @@ name = "Local"
         }
     }
 
+    #[test]
+    fn current_mut() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let var = EnvVar::new("env");
+        let mut envs = fixture();
+        var.set("stage");
+        envs.current_mut().expect("Current is not Stage!").key = Some("stage-key".to_string());
+        assert_eq!(
+            envs.get(&Environment::Stage)
+                .and_then(|e| e.key().as_deref()),
+            Some("stage-key")
+        );
+
+        let _local = envs.remove(&Environment::Local);
+        var.set("local");
+        assert!(envs.current_mut().is_err());
+    }
+
+    #[test]
+    fn current_or() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let var = EnvVar::new("env");
+        let mut envs = fixture();
+        var.remove();
+        assert_eq!(
+            envs.current_or(&Environment::Dev)
+                .ok()
+                .map(RuntimeEnv::name),
+            Some(&"Development".to_string())
+        );
+        var.set("stage");
+        assert_eq!(
+            envs.current_or(&Environment::Dev)
+                .ok()
+                .map(RuntimeEnv::name),
+            Some(&"Stage".to_string())
+        );
+        var.set("blah");
+        assert!(envs.current_or(&Environment::Dev).is_err());
+        var.remove();
+        let _dev = envs.remove(&Environment::Dev);
+        assert!(envs.current_or(&Environment::Dev).is_err());
+        assert_eq!(
+            envs.current_or_default().ok().map(RuntimeEnv::name),
+            Some(&"Local".to_string())
+        );
+    }
+
+    #[test]
+    fn from_layered() {
+        #[derive(Debug, Deserialize)]
+        struct Database {
+            host: String,
+            port: u16,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            name: String,
+            db: Database,
+        }
+
+        let var = EnvVar::new("TOMLENV_LAYERED");
+        let dir = env::temp_dir().join("tomlenv-layered");
+        std::fs::create_dir_all(&dir).expect("Unable to create layered dir");
+        std::fs::write(
+            dir.join("base.toml"),
+            "name = \"Base\"\n\n[db]\nhost = \"localhost\"\nport = 5432\n",
+        )
+        .expect("Unable to write base.toml");
+        std::fs::write(dir.join("dev.toml"), "[db]\nhost = \"dev.db\"\n")
+            .expect("Unable to write dev.toml");
+
+        var.set("development");
+        let config: Config =
+            Environments::<Environment, Config>::from_layered(&dir, "TOMLENV_LAYERED")
+                .expect("Unable to load layered config");
+        assert_eq!(config.name, "Base");
+        assert_eq!(config.db.host, "dev.db");
+        assert_eq!(config.db.port, 5432);
+
+        var.set("prod");
+        assert!(
+            Environments::<Environment, Config>::from_layered(&dir, "TOMLENV_LAYERED").is_err()
+        );
+        var.set("../base");
+        assert!(
+            Environments::<Environment, Config>::from_layered(&dir, "TOMLENV_LAYERED").is_err()
+        );
+        var.remove();
+
+        let mut vars = HashMap::new();
+        let _prev = vars.insert("APP_ENV".to_string(), "dev".to_string());
+        let config: Config =
+            Environments::<Environment, Config>::from_layered_with(&dir, "APP_ENV", &vars)
+                .expect("Unable to load layered config");
+        assert_eq!(config.db.host, "dev.db");
+        assert!(Environments::<Environment, Config>::from_layered_with(
+            &dir,
+            "TOMLENV_LAYERED",
+            &vars
+        )
+        .is_err());
+        std::fs::remove_dir_all(&dir).expect("Unable to remove layered dir");
+    }
+
+    #[test]
+    fn from_layered_string_env() {
+        let root = env::temp_dir().join("tomlenv-layered-string");
+        let dir = root.join("envs");
+        std::fs::create_dir_all(&dir).expect("Unable to create layered dir");
+        std::fs::write(dir.join("base.toml"), "name = \"Base\"\n")
+            .expect("Unable to write base.toml");
+        std::fs::write(dir.join("eu-west.toml"), "name = \"Ireland\"\n")
+            .expect("Unable to write eu-west.toml");
+        std::fs::write(root.join("secrets.toml"), "name = \"Secret\"\n")
+            .expect("Unable to write secrets.toml");
+
+        let layered = |name: &str| {
+            let mut vars = HashMap::new();
+            let _prev = vars.insert("APP_ENV".to_string(), name.to_string());
+            Environments::<StringEnv, RuntimeEnv>::from_layered_with(&dir, "APP_ENV", &vars)
+                .map(|config| config.name)
+                .map_err(|e| e.reason().clone())
+        };
+        assert_eq!(layered("eu-west"), Ok("Ireland".to_string()));
+        for name in &[
+            "../secrets",
+            "..",
+            ".",
+            "eu/west",
+            "eu-west/",
+            "/secrets",
+            "..\\secrets",
+        ] {
+            assert_eq!(
+                layered(name),
+                Err(format!(
+                    "invalid current environment '{name}' from 'APP_ENV'"
+                ))
+            );
+        }
+        std::fs::remove_dir_all(&root).expect("Unable to remove layered dir");
+    }
+
+    #[test]
+    fn current_lazy_from_reader() {
+        let var = EnvVar::new("TOMLENV_LAZY");
+        var.set("development");
+        let current: RuntimeEnv =
+            Environments::<Environment, RuntimeEnv>::current_lazy_from_reader(
+                &mut Cursor::new(EXPECTED_TOML_STR),
+                "TOMLENV_LAZY",
+            )
+            .expect("Unable to load the current environment");
+        assert_eq!(current.name(), "Development");
+
+        // Only the selected environment has to match the config type.
+        let toml = "[envs.prod]\nname = 1\n\n[envs.dev]\nname = \"Development\"\n";
+        let current = Environments::<Environment, RuntimeEnv>::current_lazy_from_reader(
+            &mut Cursor::new(toml),
+            "TOMLENV_LAZY",
+        );
+        assert_eq!(
+            current.ok().map(|env| env.name),
+            Some("Development".to_string())
+        );
+
+        var.set("local");
+        assert!(
+            Environments::<Environment, RuntimeEnv>::current_lazy_from_reader(
+                &mut Cursor::new(toml),
+                "TOMLENV_LAZY",
+            )
+            .is_err()
+        );
+        var.remove();
+
+        let mut vars = HashMap::new();
+        let _prev = vars.insert("APP_ENV".to_string(), "dev".to_string());
+        let current = Environments::<Environment, RuntimeEnv>::current_lazy_from_reader_with(
+            &mut Cursor::new(toml),
+            "APP_ENV",
+            &vars,
+        );
+        assert_eq!(
+            current.ok().map(|env| env.name),
+            Some("Development".to_string())
+        );
+    }
+
+    #[test]
+    fn current_for_profile() {
+        let var = EnvVar::new("TOMLENV_PROFILE");
+        let mut envs = fixture();
+        envs.set_var_name("TOMLENV_PROFILE".to_string());
+        var.remove();
+        let expected = if cfg!(debug_assertions) {
+            "Development"
+        } else {
+            "Production"
+        };
+        assert_eq!(
+            envs.current_for_profile().map(RuntimeEnv::name).ok(),
+            Some(&expected.to_string())
+        );
+        assert_eq!(
+            envs.current_for_profile_with(&Environment::Test, &Environment::Test)
+                .map(RuntimeEnv::name)
+                .ok(),
+            Some(&"Test".to_string())
+        );
+        var.set("stage");
+        assert_eq!(
+            envs.current_for_profile().map(RuntimeEnv::name).ok(),
+            Some(&"Stage".to_string())
+        );
+    }
+
+    #[test]
+    fn select() {
+        let var = EnvVar::new("TOMLENV_SELECT");
+        let mut envs = fixture();
+        envs.set_var_name("TOMLENV_SELECT".to_string());
+        var.set("dev");
+        envs.select(Environment::Stage);
+        assert_eq!(envs.selected(), Some(&Environment::Stage));
+        assert_eq!(
+            envs.current().map(RuntimeEnv::name).ok(),
+            Some(&"Stage".to_string())
+        );
+        assert_eq!(
+            envs.current_mut().map(|config| config.name().clone()).ok(),
+            Some("Stage".to_string())
+        );
+
+        assert_eq!(envs.unselect(), Some(Environment::Stage));
+        assert_eq!(
+            envs.current().map(RuntimeEnv::name).ok(),
+            Some(&"Development".to_string())
+        );
+
+        let _local = envs.remove(&Environment::Local);
+        envs.select(Environment::Local);
+        let err = envs.current().expect_err("local is not present");
+        assert_eq!(err.reason(), "the selected environment has no config");
+    }
+
+    #[test]
+    fn select_accessors() {
+        let var = EnvVar::new("TOMLENV_SELECT_ACCESSORS");
+        let mut envs = fixture();
+        envs.set_var_name("TOMLENV_SELECT_ACCESSORS".to_string());
+        var.set("dev");
+        envs.select(Environment::Stage);
+        let stage = Some("Stage".to_string());
+        let name = |config: &RuntimeEnv| config.name().clone();
+
+        assert_eq!(envs.current_key().ok(), Some(Environment::Stage));
+        assert_eq!(
+            envs.current_entry()
+                .map(|(environment, config)| (environment, name(config)))
+                .ok(),
+            Some((Environment::Stage, "Stage".to_string()))
+        );
+        assert_eq!(envs.current_opt().ok().flatten().map(name), stage);
+        assert_eq!(envs.current_or(&Environment::Prod).map(name).ok(), stage);
+        assert_eq!(
+            envs.current_or_else(|| Environment::Prod).map(name).ok(),
+            stage
+        );
+        assert_eq!(
+            envs.current_for_profile_with(&Environment::Prod, &Environment::Prod)
+                .map(name)
+                .ok(),
+            stage
+        );
+        let merged = envs.current_merged().expect("Stage is selected");
+        assert_eq!(merged.name(), "Stage");
+        assert_eq!(merged.key().as_deref(), Some("abcd-123-efg-45"));
+        assert_eq!(
+            envs.current_chain()
+                .map(|chain| chain.into_iter().map(|(key, _)| *key).collect::<Vec<_>>())
+                .ok(),
+            Some(vec![Environment::Stage, Environment::Prod])
+        );
+        let mut vars = HashMap::new();
+        let _prev = vars.insert("TOMLENV_SELECT_ACCESSORS".to_string(), "dev".to_string());
+        assert_eq!(envs.current_with_reader(&vars).map(name).ok(), stage);
+
+        var.remove();
+        assert_eq!(envs.current_opt().ok().flatten().map(name), stage);
+        let envs = envs
+            .with_env_overrides("TOMLENV_SELECT_ACCESSORS_OVERRIDE")
+            .expect("Unable to apply overrides");
+        assert_eq!(envs.selected(), Some(&Environment::Stage));
+        assert_eq!(envs.current().map(name).ok(), stage);
+    }
+
+    #[test]
+    fn current_cache() {
+        let var = EnvVar::new("TOMLENV_CACHE");
+        let mut envs = fixture();
+        envs.set_var_name("TOMLENV_CACHE".to_string());
+        var.set("dev");
+        assert_eq!(
+            envs.current().map(RuntimeEnv::name).ok(),
+            Some(&"Development".to_string())
+        );
+        assert_eq!(
+            envs.cache.lock().clone(),
+            Some(("dev".to_string(), Environment::Dev))
+        );
+        assert_eq!(
+            envs.current().map(RuntimeEnv::name).ok(),
+            Some(&"Development".to_string())
+        );
+
+        var.set("prod");
+        assert_eq!(
+            envs.current().map(RuntimeEnv::name).ok(),
+            Some(&"Production".to_string())
+        );
+        assert_eq!(
+            envs.cache.lock().clone(),
+            Some(("prod".to_string(), Environment::Prod))
+        );
+
+        let _current = envs
+            .current_from_mut("TOMLENV_CACHE")
+            .expect("Current is not Production!");
+        assert_eq!(
+            envs.cache.lock().clone(),
+            Some(("prod".to_string(), Environment::Prod))
+        );
+        var.set("test");
+        let test = envs
+            .current_from_mut("TOMLENV_CACHE")
+            .expect("Current is not Test!");
+        assert_eq!(test.name(), "Test");
+        assert_eq!(
+            envs.cache.lock().clone(),
+            Some(("test".to_string(), Environment::Test))
+        );
+        var.set("prod");
+
+        envs.invalidate_current_cache();
+        assert_eq!(envs.cache.lock().clone(), None);
+        let _prod = envs.remove(&Environment::Prod);
+        assert!(envs.current().is_err());
+    }
+
+    #[test]
+    fn current_from_file() {
+        let envs = fixture();
+        let env_file = env::temp_dir().join("tomlenv-app-env");
+        std::fs::write(&env_file, "stage\n").expect("Unable to write environment file");
+        assert_eq!(
+            envs.current_from_file(&env_file).map(RuntimeEnv::name).ok(),
+            Some(&"Stage".to_string())
+        );
+
+        std::fs::write(&env_file, "blah").expect("Unable to write environment file");
+        let err = envs
+            .current_from_file(&env_file)
+            .expect_err("blah is not an environment");
+        assert_eq!(
+            err.reason(),
+            &format!(
+                "invalid current environment 'blah' from '{}'",
+                env_file.display()
+            )
+        );
+
+        remove_file(&env_file).expect("Unable to remove tmp 'tomlenv-app-env'");
+        assert!(envs.current_from_file(&env_file).is_err());
+    }
+
+    #[test]
+    fn current_trimmed() {
+        let var = EnvVar::new("TOMLENV_TRIMMED");
+        let mut envs = fixture();
+        envs.set_var_name("TOMLENV_TRIMMED".to_string());
+        var.set(" prod\n");
+        assert_eq!(
+            envs.current().map(RuntimeEnv::name).ok(),
+            Some(&"Production".to_string())
+        );
+        assert_eq!(envs.current_key().ok(), Some(Environment::Prod));
+        assert_eq!(
+            envs.current_opt().ok().flatten().map(RuntimeEnv::name),
+            Some(&"Production".to_string())
+        );
+        var.set("\tst age ");
+        let err = envs.current().expect_err("st age is not an environment");
+        assert_eq!(
+            err.reason(),
+            "invalid current environment 'st age' from 'TOMLENV_TRIMMED'"
+        );
+    }
+
+    #[test]
+    fn current_or_else() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let var = EnvVar::new("env");
+        let mut envs = fixture();
+        var.remove();
+        assert_eq!(
+            envs.current_or_else(|| Environment::Test)
+                .ok()
+                .map(RuntimeEnv::name),
+            Some(&"Test".to_string())
+        );
+        var.set("stage");
+        assert_eq!(
+            envs.current_or_else(|| unreachable!("env is set"))
+                .ok()
+                .map(RuntimeEnv::name),
+            Some(&"Stage".to_string())
+        );
+        var.set("blah");
+        assert!(envs.current_or_else(|| Environment::Test).is_err());
+        var.remove();
+        let _test = envs.remove(&Environment::Test);
+        assert!(envs.current_or_else(|| Environment::Test).is_err());
+    }
+
+    #[test]
+    fn current_opt() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let var = EnvVar::new("env");
+        let mut envs = fixture();
+        var.remove();
+        assert!(envs
+            .current_opt()
+            .expect("Unset env should not error")
+            .is_none());
+        var.set("test");
+        assert_eq!(
+            envs.current_opt()
+                .expect("Current is not Test!")
+                .map(RuntimeEnv::name),
+            Some(&"Test".to_string())
+        );
+        var.set("blah");
+        assert!(envs.current_opt().is_err());
+        let _test = envs.remove(&Environment::Test);
+        var.set("test");
+        assert!(envs.current_opt().is_err());
+    }
+
+    #[test]
+    fn current_entry() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let var = EnvVar::new("env");
+        let envs = fixture();
+        var.set("Staging");
+        assert_eq!(envs.current_key().ok(), Some(Environment::Stage));
+        let (key, config) = envs.current_entry().expect("Current is not Stage!");
+        assert_eq!(key, Environment::Stage);
+        assert_eq!(config.name(), "Stage");
+        var.set("blah");
+        assert!(envs.current_key().is_err());
+    }
+
     #[test]
     fn current_from() {
+        let var = EnvVar::new(TOMLENV);
         match try_decode(EXPECTED_TOML_STR) {
             Ok(ref envs) => {
-                env::set_var(TOMLENV, "prod");
+                var.set("prod");
                 match try_current_from(TOMLENV, envs, "Production") {
                     Ok(_) => assert!(true, "Found Production Env"),
                     Err(_) => assert!(false, "Current is not Production!"),
                 }
-                env::set_var(TOMLENV, "stage");
+                var.set("stage");
                 match try_current_from(TOMLENV, envs, "Stage") {
                     Ok(_) => assert!(true, "Found Stage Env"),
                     Err(_) => assert!(false, "Current is not Stage!"),
                 }
-                env::set_var(TOMLENV, "test");
+                var.set("test");
                 match try_current_from(TOMLENV, envs, "Test") {
                     Ok(_) => assert!(true, "Found Test Env"),
                     Err(_) => assert!(false, "Current is not Test!"),
                 }
-                env::set_var(TOMLENV, "dev");
+                var.set("dev");
                 match try_current_from(TOMLENV, envs, "Development") {
                     Ok(_) => assert!(true, "Found Development Env"),
                     Err(_) => assert!(false, "Current is not Development!"),
                 }
-                env::set_var(TOMLENV, "local");
+                var.set("local");
                 match try_current_from(TOMLENV, envs, "Local") {
                     Ok(_) => assert!(true, "Found Local Env"),
                     Err(_) => assert!(false, "Current is not Local!"),
@@ -372,6 +3196,502 @@ name = "Local"
     }
 
     #[test]
+    fn current_from_dynamic() {
+        let name = format!("{TOMLENV}_{}", "DYNAMIC");
+        let var = EnvVar::new(&name);
+        let envs = fixture();
+        var.set("dev");
+        try_current_from(&name, &envs, "Development").expect("Current is not Development!");
+    }
+
+    #[test]
+    fn current_from_value() {
+        let envs = fixture();
+        let current = envs
+            .current_from_value("dev")
+            .expect("Current is not Development!");
+        assert_eq!(current.name(), "Development");
+        assert!(envs.current_from_value("blah").is_err());
+    }
+
+    #[test]
+    fn get_by_name() {
+        let envs =
+            try_decode("[envs.prod]\nname = \"Production\"\n").expect("Unable to decode TOML");
+        let prod = envs.get_by_name("prod").expect("prod is present");
+        assert_eq!(prod.name(), "Production");
+
+        let missing = envs.get_by_name("dev").expect_err("dev is not present");
+        assert_eq!(missing.reason(), "invalid environment 'dev'");
+        assert!(missing.source().is_none());
+
+        let unknown = envs
+            .get_by_name("blah")
+            .expect_err("blah is not an environment");
+        assert_eq!(unknown.reason(), "invalid environment 'blah'");
+        assert!(unknown.source().is_some());
+    }
+
+    #[test]
+    fn caller() {
+        let envs = fixture();
+        let line = line!() + 1;
+        let err = envs.current_from_value("blah").expect_err("invalid");
+        assert_eq!(err.caller().file(), file!());
+        assert_eq!(err.caller().line(), line);
+
+        let line = line!() + 1;
+        let err = Environments::<Environment, RuntimeEnv>::from_str("=").expect_err("invalid TOML");
+        assert_eq!(err.caller().line(), line);
+
+        let path = env::temp_dir().join("tomlenv-caller-missing.toml");
+        let line = line!() + 1;
+        let err = Environments::<Environment, RuntimeEnv>::from_path(&path).expect_err("missing");
+        assert_eq!(err.caller().line(), line);
+    }
+
+    #[test]
+    fn current_borrowed() {
+        #[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        struct Region(String);
+
+        impl TryFrom<&str> for Region {
+            type Error = Error;
+
+            fn try_from(region: &str) -> Result<Self> {
+                if region.is_empty() {
+                    Err(Error::invalid_runtime_environment(region))
+                } else {
+                    Ok(Region(region.to_string()))
+                }
+            }
+        }
+
+        let var = EnvVar::new("TOMLENV_BORROWED");
+        let mut envs: Environments<Region, &str> = vec![(Region("eu".to_string()), "Europe")]
+            .into_iter()
+            .collect();
+        var.set(" eu\n");
+        assert_eq!(
+            envs.current_from_borrowed("TOMLENV_BORROWED").ok(),
+            Some(&"Europe")
+        );
+        assert_eq!(
+            envs.cache
+                .lock()
+                .as_ref()
+                .map(|(value, region)| (value.as_str(), &region.0[..])),
+            Some((" eu\n", "eu"))
+        );
+        var.set("");
+        assert!(envs.current_from_borrowed("TOMLENV_BORROWED").is_err());
+        var.remove();
+        assert!(envs.current_from_borrowed("TOMLENV_BORROWED").is_err());
+
+        envs.set_var_name("TOMLENV_BORROWED".to_string());
+        assert!(envs.current_borrowed().is_err());
+        let mut vars = HashMap::new();
+        let _prev = vars.insert("TOMLENV_BORROWED".to_string(), "eu".to_string());
+        assert_eq!(
+            envs.current_borrowed_with_reader(&vars).ok(),
+            Some(&"Europe")
+        );
+        assert_eq!(
+            envs.current_from_borrowed_with_reader("TOMLENV_BORROWED", &vars)
+                .ok(),
+            Some(&"Europe")
+        );
+        envs.select(Region("eu".to_string()));
+        assert_eq!(envs.current_borrowed().ok(), Some(&"Europe"));
+    }
+
+    #[cfg(not(feature = "indexmap"))]
+    #[test]
+    fn serialize_in_hierarchy_order() {
+        crate::hierarchy! {
+            Tier {
+                Prod => "prod",
+                Cert => "cert",
+                Alpha => "alpha",
+            }
+        }
+
+        let toml = "[envs.alpha]\nname = \"Alpha\"\n\n[envs.cert]\nname = \"Cert\"\n\n[envs.prod]\nname = \"Prod\"\n";
+        let envs: Environments<Tier, RuntimeEnv> =
+            Environments::from_str(toml).expect("Unable to decode TOML");
+        let expected = "[envs.prod]\nname = \"Prod\"\n\n[envs.cert]\nname = \"Cert\"\n\n[envs.alpha]\nname = \"Alpha\"\n";
+        assert_eq!(envs.to_string().ok().as_deref(), Some(expected));
+
+        let env = |name: &str| RuntimeEnv {
+            name: name.to_string(),
+            key: None,
+        };
+        let envs: Environments<Tier, RuntimeEnv> = vec![
+            (Tier::Alpha, env("Alpha")),
+            (Tier::Prod, env("Prod")),
+            (Tier::Cert, env("Cert")),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(envs.to_string().ok().as_deref(), Some(expected));
+    }
+
+    #[cfg(feature = "indexmap")]
+    #[test]
+    fn serialize_in_declaration_order() {
+        let toml = "[envs.local]\nname = \"Local\"\n\n[envs.prod]\nname = \"Production\"\n\n[envs.dev]\nname = \"Development\"\n";
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_str(toml).expect("Unable to decode TOML");
+        assert_eq!(envs.to_string().ok().as_deref(), Some(toml));
+        let mut envs = envs
+            .with_env_overrides("TOMLENV_DECLARATION_ORDER")
+            .expect("Unable to apply overrides");
+        assert_eq!(envs.to_string().ok().as_deref(), Some(toml));
+        let chain: Vec<_> = envs
+            .chain(&Environment::Dev)
+            .expect("Dev is present")
+            .map(|(key, _)| *key)
+            .collect();
+        assert_eq!(
+            chain,
+            vec![Environment::Dev, Environment::Prod, Environment::Local]
+        );
+
+        let prod = envs.remove(&Environment::Prod);
+        let keys: Vec<_> = envs.keys().copied().collect();
+        assert_eq!(keys, vec![Environment::Local, Environment::Dev]);
+
+        let prod = prod.expect("Prod was present");
+        let _prev = envs.insert(Environment::Prod, prod);
+        assert_eq!(envs.keys().last(), Some(&Environment::Prod));
+    }
+
+    #[test]
+    fn example() {
+        #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+        struct Config {
+            name: String,
+            port: u16,
+        }
+
+        let envs: Environments<Environment, Config> = Environments::example();
+        assert_eq!(envs.len(), 5);
+        assert!(envs.values().all(|config| *config == Config::default()));
+
+        let toml = envs.to_string().expect("Unable to encode Environments!");
+        let decoded: Environments<Environment, Config> =
+            Environments::from_str(&toml).expect("Unable to decode Environments!");
+        assert_eq!(decoded.get(&Environment::Local), Some(&Config::default()));
+    }
+
+    #[test]
+    fn template() {
+        #[derive(Clone, Debug, Default, Deserialize, Serialize)]
+        struct Template {
+            name: String,
+            port: u16,
+        }
+
+        let template = Environments::<Environment, Template>::template_string()
+            .expect("Unable to build template");
+        assert!(template.contains("[envs.prod]\nname = \"\"\nport = 0\n"));
+        assert!(template.contains("[envs.local]\n"));
+
+        let path = env::temp_dir().join("tomlenv-template.toml");
+        Environments::<Environment, Template>::write_template(&path)
+            .expect("Unable to write template");
+        let envs: Environments<Environment, Template> =
+            Environments::from_path(&path).expect("Unable to load template");
+        remove_file(&path).expect("Unable to remove template");
+        assert_eq!(envs.len(), 5);
+    }
+
+    #[test]
+    fn validate() {
+        let mut envs = fixture();
+        envs.validate(Environment::all())
+            .expect("All environments should be present");
+        let _prev = envs.remove(&Environment::Stage);
+        let _prev = envs.remove(&Environment::Local);
+        let err = envs
+            .validate(Environment::all())
+            .expect_err("Stage and Local should be missing");
+        assert_eq!(err.reason(), "missing environments: stage, local");
+    }
+
+    #[test]
+    fn current_with_reader() {
+        let envs = fixture();
+        let mut vars = HashMap::new();
+        let _prev = vars.insert("env".to_string(), "stage".to_string());
+        let _prev = vars.insert("TOMLENV_READER".to_string(), "test".to_string());
+        let current = envs
+            .current_with_reader(&vars)
+            .expect("Current is not Stage!");
+        assert_eq!(current.name(), "Stage");
+        let current = envs
+            .current_from_with_reader("TOMLENV_READER", &vars)
+            .expect("Current is not Test!");
+        assert_eq!(current.name(), "Test");
+        assert!(envs
+            .current_from_with_reader("TOMLENV_READER_MISSING", &vars)
+            .is_err());
+    }
+
+    #[test]
+    fn current_from_mut() {
+        let var = "TOMLENV_MUT";
+        let env_var = EnvVar::new(var);
+        let mut envs = fixture();
+        env_var.set("test");
+        envs.current_from_mut(var)
+            .expect("Current is not Test!")
+            .name = "Test Override".to_string();
+        try_current_from(var, &envs, "Test Override").expect("Current is not Test Override!");
+
+        env_var.set("blah");
+        match envs.current_from_mut(var) {
+            Ok(_) => panic!("'blah' is not a valid environment"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "env: invalid current environment 'blah' from 'TOMLENV_MUT': env: invalid runtime environment 'blah'"
+            ),
+        }
+
+        env_var.remove();
+        match envs.current_from_mut(var) {
+            Ok(_) => panic!("An unset variable should not resolve"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "env: unable to read environment variable 'TOMLENV_MUT': environment variable not found"
+            ),
+        }
+
+        let mut vars = HashMap::new();
+        let _prev = vars.insert(var.to_string(), "stage".to_string());
+        envs.current_from_mut_with_reader(var, &vars)
+            .expect("Current is not Stage!")
+            .name = "Stage Override".to_string();
+        assert_eq!(
+            envs.current_from_with_reader(var, &vars)
+                .map(RuntimeEnv::name)
+                .ok(),
+            Some(&"Stage Override".to_string())
+        );
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "tokio")]
+    async fn from_path_async() {
+        let path = env::temp_dir().join("tomlenv-from-path-async.toml");
+        std::fs::write(&path, EXPECTED_TOML_STR).expect("Unable to write TOML");
+        let envs: Environments<Environment, RuntimeEnv> = Environments::from_path_async(&path)
+            .await
+            .expect("Unable to load Environments");
+        assert_eq!(envs.len(), 5);
+        remove_file(&path).expect("Unable to remove TOML");
+
+        let missing: Result<Environments<Environment, RuntimeEnv>> =
+            Environments::from_path_async(&path).await;
+        assert!(missing.is_err());
+    }
+
+    #[cfg(feature = "http")]
+    fn serve_once(status: &str, body: &'static str) -> String {
+        use std::{
+            io::{Read, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Unable to bind listener");
+        let addr = listener.local_addr().expect("Unable to get address");
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        let _handle = thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut request = [0; 1024];
+                let _read = stream.read(&mut request);
+                let _written = stream.write_all(response.as_bytes());
+            }
+        });
+        format!("http://{addr}/env.toml")
+    }
+
+    #[test]
+    #[cfg(feature = "http")]
+    fn from_url() {
+        let url = serve_once("200 OK", EXPECTED_TOML_STR);
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_url(&url).expect("Unable to fetch Environments");
+        assert_eq!(envs.len(), 5);
+
+        let url = serve_once("404 Not Found", "");
+        let err = Environments::<Environment, RuntimeEnv>::from_url(&url)
+            .expect_err("A 404 should be an error");
+        assert_eq!(err.kind(), ErrCode::HttpClient);
+        assert_eq!(
+            err.reason(),
+            &format!("unable to fetch '{url}': status 404")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn from_gz_path() {
+        use flate2::{write::GzEncoder, Compression};
+        use std::{fs, io::Write};
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(EXPECTED_TOML_STR.as_bytes())
+            .expect("Unable to compress TOML");
+        let compressed = encoder.finish().expect("Unable to compress TOML");
+
+        let path = env::temp_dir().join("tomlenv-gz.toml.gz");
+        fs::write(&path, compressed).expect("Unable to write TOML");
+        let envs: Result<Environments<Environment, RuntimeEnv>> = Environments::from_gz_path(&path);
+        assert_eq!(envs.map(|envs| envs.len()).ok(), Some(5));
+
+        fs::write(&path, EXPECTED_TOML_STR).expect("Unable to write TOML");
+        let err = Environments::<Environment, RuntimeEnv>::from_gz_path(&path)
+            .expect_err("Uncompressed TOML should not decompress");
+        assert_eq!(err.kind(), ErrCode::Io);
+        remove_file(&path).expect("Unable to remove TOML");
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json() {
+        let envs = fixture();
+        let json = envs.to_json_string().expect("Unable to encode JSON");
+        let from_str: Environments<Environment, RuntimeEnv> =
+            Environments::from_json_str(&json).expect("Unable to decode JSON");
+        assert_eq!(from_str.len(), 5);
+        let from_reader: Environments<Environment, RuntimeEnv> =
+            Environments::from_json_reader(Cursor::new(json)).expect("Unable to decode JSON");
+        assert_eq!(
+            from_reader.get(&Environment::Prod).map(RuntimeEnv::name),
+            Some(&"Production".to_string())
+        );
+        assert!(Environments::<Environment, RuntimeEnv>::from_json_str("{").is_err());
+
+        let json = r#"{
+            "defaults": { "key": "default-key" },
+            "envs": {
+                "stage": { "inherits": "prod", "name": "Stage" },
+                "prod": { "name": "Production", "key": null }
+            }
+        }"#;
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_json_str(json).expect("Unable to decode JSON");
+        assert_eq!(
+            envs.get(&Environment::Stage).map(|env| env.key.as_deref()),
+            Some(Some("default-key"))
+        );
+        #[cfg(feature = "indexmap")]
+        assert_eq!(
+            envs.keys().collect::<Vec<_>>(),
+            vec![&Environment::Stage, &Environment::Prod]
+        );
+        let err = Environments::<Environment, RuntimeEnv>::from_json_str(r#"{"envs": {}}"#)
+            .expect_err("An empty envs table has no environments");
+        assert_eq!(err.reason(), "no environments defined");
+    }
+
+    #[test]
+    #[cfg(feature = "yaml")]
+    fn yaml() {
+        let envs = fixture();
+        let yaml = envs.to_yaml_string().expect("Unable to encode YAML");
+        let from_str: Environments<Environment, RuntimeEnv> =
+            Environments::from_yaml_str(&yaml).expect("Unable to decode YAML");
+        assert_eq!(from_str.len(), 5);
+        let from_reader: Environments<Environment, RuntimeEnv> =
+            Environments::from_yaml_reader(Cursor::new(yaml)).expect("Unable to decode YAML");
+        assert_eq!(
+            from_reader.get(&Environment::Prod).map(RuntimeEnv::name),
+            Some(&"Production".to_string())
+        );
+        assert!(Environments::<Environment, RuntimeEnv>::from_yaml_str("envs: [").is_err());
+
+        let yaml = "defaults:\n  key: default-key\nenvs:\n  prod:\n    name: Production\n  stage:\n    inherits: prod\n    name: Stage\n";
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_yaml_str(yaml).expect("Unable to decode YAML");
+        assert_eq!(
+            envs.get(&Environment::Stage).map(|env| env.key.as_deref()),
+            Some(Some("default-key"))
+        );
+        let err = Environments::<Environment, RuntimeEnv>::from_yaml_str("envs: {}\n")
+            .expect_err("An empty envs table has no environments");
+        assert_eq!(err.reason(), "no environments defined");
+    }
+
+    #[test]
+    #[cfg(feature = "derive")]
+    fn derive_hierarchy() {
+        use crate::{Enumerable, Hierarchy};
+
+        #[derive(Clone, Copy, Debug, Eq, Hash, Hierarchy, Ord, PartialEq, PartialOrd)]
+        enum MyHierarchy {
+            Prod,
+            #[env("ce")]
+            Cert,
+            Local,
+        }
+
+        let envs: Environments<MyHierarchy, RuntimeEnv> = Environments::from_str(
+            "[envs.prod]\nname = \"Production\"\n\n[envs.ce]\nname = \"Certification\"\n",
+        )
+        .expect("Unable to decode TOML to Environments!");
+        assert_eq!(
+            envs.current_from_value("ce").map(RuntimeEnv::name).ok(),
+            Some(&"Certification".to_string())
+        );
+        assert_eq!(MyHierarchy::Cert.to_string(), "ce");
+        assert_eq!(
+            MyHierarchy::try_from("local").ok(),
+            Some(MyHierarchy::Local)
+        );
+        assert!(envs.current_from_value("cert").is_err());
+
+        let toml = envs.to_string().expect("Unable to encode Environments!");
+        assert!(toml.contains("[envs.ce]"));
+        assert!(Environments::<MyHierarchy, RuntimeEnv>::from_str("[envs.cert]\n").is_err());
+        assert_eq!(
+            MyHierarchy::all(),
+            vec![MyHierarchy::Prod, MyHierarchy::Cert, MyHierarchy::Local]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "clap")]
+    fn from_matches() {
+        let config_dir = env::temp_dir();
+        let env_toml = config_dir.join("environments.toml");
+        try_decode(EXPECTED_TOML_STR)
+            .and_then(|envs| envs.to_path(&env_toml))
+            .expect("Unable to write 'environments.toml'");
+
+        let cli = App::new("env-from-matches").arg(
+            Arg::with_name("config_dir")
+                .long("config-dir")
+                .takes_value(true),
+        );
+        let dir = format!("{}", config_dir.display());
+        let matches = cli.get_matches_from(vec!["env-from-matches", "--config-dir", &dir]);
+        let envs: Environments<Environment, RuntimeEnv> =
+            Environments::from_matches(&matches, "config_dir", "environments.toml")
+                .expect("Unable to deserialize environments");
+        assert_eq!(envs.len(), 5);
+
+        remove_file(env_toml).expect("Unable to remove tmp 'environments.toml'");
+    }
+
+    #[test]
+    #[cfg(feature = "clap")]
     fn try_from() {
         if let Some(data_local_dir) = dirs::data_local_dir() {
             let env_toml = data_local_dir.join("env.toml");
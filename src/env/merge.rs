@@ -0,0 +1,63 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` config inheritance
+/// Merge config from a more restrictive parent environment into this one.
+///
+/// Implement this on your environment config struct to enable inheritance via
+/// `Environments::resolve`.  Fields that are unset in `self` should be filled
+/// from `parent`, which is usually as simple as calling `merge` on each
+/// `Option` field.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::Merge;
+/// struct MyAppEnv {
+///     name: Option<String>,
+///     key: Option<String>,
+/// }
+///
+/// impl Merge for MyAppEnv {
+///     fn merge(&mut self, parent: &Self) {
+///         self.name.merge(&parent.name);
+///         self.key.merge(&parent.key);
+///     }
+/// }
+/// ```
+pub trait Merge {
+    /// Fill any unset values in `self` from `parent`.
+    fn merge(&mut self, parent: &Self);
+}
+
+impl<V> Merge for Option<V>
+where
+    V: Clone,
+{
+    fn merge(&mut self, parent: &Self) {
+        if self.is_none() {
+            self.clone_from(parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Merge;
+
+    #[test]
+    fn merge_option() {
+        let mut unset: Option<String> = None;
+        unset.merge(&Some("parent".to_string()));
+        assert_eq!(unset, Some("parent".to_string()));
+
+        let mut set = Some("child".to_string());
+        set.merge(&Some("parent".to_string()));
+        assert_eq!(set, Some("child".to_string()));
+    }
+}
@@ -7,8 +7,34 @@
 // modified, or distributed except according to those terms.
 
 //! `tomlenv` environment module
+mod builder;
+mod cache;
+mod diff;
+mod encoding;
 mod environment;
 mod environments;
+mod merge;
+#[cfg(any(feature = "json", feature = "yaml"))]
+mod nullable;
+#[cfg(feature = "indexmap")]
+mod order;
+mod secret;
+mod shared;
+mod string;
+mod value;
+mod var;
+#[cfg(feature = "notify")]
+mod watch;
 
+pub use builder::EnvironmentsBuilder;
+pub use diff::EnvDiff;
+pub use encoding::Base64;
 pub use environment::Environment;
 pub use environments::Environments;
+pub use merge::Merge;
+pub use secret::Secret;
+pub use shared::SharedEnvironments;
+pub use string::StringEnv;
+pub use var::{StdVarReader, VarReader};
+#[cfg(feature = "notify")]
+pub use watch::EnvironmentsWatcher;
@@ -0,0 +1,136 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` documents from formats with `null`
+use serde::de::{Deserialize, Deserializer, Error, MapAccess, SeqAccess, Visitor};
+use std::{convert::TryFrom, fmt};
+use toml::{value::Table, Value};
+
+/// A TOML value read from a format that has `null`, e.g. JSON or YAML.  TOML
+/// has no `null`, so one is read as a missing value, as an unset `Option` is
+/// written, and is dropped from the table or array holding it.
+pub(crate) struct Nullable(Option<Value>);
+
+impl Nullable {
+    /// The TOML document, or an empty one if the whole document is `null`
+    pub(crate) fn into_document(self) -> Value {
+        self.0.unwrap_or_else(|| Value::Table(Table::new()))
+    }
+}
+
+impl<'de> Deserialize<'de> for Nullable {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(NullableVisitor)
+    }
+}
+
+/// Builds a `Nullable` from any self-describing format
+struct NullableVisitor;
+
+impl<'de> Visitor<'de> for NullableVisitor {
+    type Value = Nullable;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("any valid TOML value or null")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<Nullable, E> {
+        Ok(Nullable(Some(Value::Boolean(value))))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Nullable, E> {
+        Ok(Nullable(Some(Value::Integer(value))))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Nullable, E>
+    where
+        E: Error,
+    {
+        match i64::try_from(value) {
+            Ok(value) => Ok(Nullable(Some(Value::Integer(value)))),
+            Err(_) => Err(E::custom(format!("{value} is too large for TOML"))),
+        }
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Nullable, E> {
+        Ok(Nullable(Some(Value::Float(value))))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Nullable, E> {
+        Ok(Nullable(Some(Value::String(value.to_string()))))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<Nullable, E> {
+        Ok(Nullable(Some(Value::String(value))))
+    }
+
+    fn visit_none<E>(self) -> Result<Nullable, E> {
+        Ok(Nullable(None))
+    }
+
+    fn visit_unit<E>(self) -> Result<Nullable, E> {
+        Ok(Nullable(None))
+    }
+
+    fn visit_some<D>(self, deserializer: D) -> Result<Nullable, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Nullable::deserialize(deserializer)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Nullable, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut array = Vec::new();
+        while let Some(Nullable(value)) = seq.next_element()? {
+            array.extend(value);
+        }
+        Ok(Nullable(Some(Value::Array(array))))
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Nullable, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut table = Table::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if let Nullable(Some(value)) = map.next_value()? {
+                let _prev = table.insert(key, value);
+            }
+        }
+        Ok(Nullable(Some(Value::Table(table))))
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod test {
+    use super::Nullable;
+    use toml::Value;
+
+    #[test]
+    fn nulls_dropped() {
+        let json = r#"{"name": "Prod", "key": null, "ports": [1, null, 2], "nested": {"a": null}}"#;
+        let document = serde_json::from_str::<Nullable>(json)
+            .map(Nullable::into_document)
+            .ok();
+        let expected: Option<Value> =
+            toml::from_str("name = \"Prod\"\nports = [1, 2]\n\n[nested]\n").ok();
+        assert_eq!(document, expected);
+
+        let document = serde_json::from_str::<Nullable>("null")
+            .map(Nullable::into_document)
+            .ok();
+        assert_eq!(document, Some(Value::Table(toml::value::Table::new())));
+        assert!(serde_json::from_str::<Nullable>("18446744073709551615").is_err());
+    }
+}
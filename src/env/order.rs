@@ -0,0 +1,81 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` environment declaration order
+use indexmap::IndexMap;
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, Visitor};
+use std::fmt;
+
+/// Read the names of the environments in the given top-level table, in the
+/// order they were declared.  Converting a document to a `toml::Value` loses
+/// this order, so it is read separately from the source.
+pub(crate) fn declared_order<'de, D>(
+    deserializer: D,
+    table_name: &str,
+) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    DeclaredOrder(table_name).deserialize(deserializer)
+}
+
+/// Collects the keys of one top-level table, ignoring everything else
+struct DeclaredOrder<'a>(&'a str);
+
+impl<'de> DeserializeSeed<'de> for DeclaredOrder<'_> {
+    type Value = Vec<String>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Vec<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DeclaredOrder<'_> {
+    type Value = Vec<String>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("a table of environments")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Vec<String>, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut names = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if key == self.0 {
+                let envs: IndexMap<String, IgnoredAny> = map.next_value()?;
+                names = envs.into_iter().map(|(name, _)| name).collect();
+            } else {
+                let _ignored: IgnoredAny = map.next_value()?;
+            }
+        }
+        Ok(names)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::declared_order;
+
+    #[test]
+    fn declared() {
+        let toml = "[defaults]\nkey = 1\n\n[envs.local]\n\n[envs.prod]\n\n[envs.dev]\n";
+        assert_eq!(
+            declared_order(&mut toml::Deserializer::new(toml), "envs").ok(),
+            Some(vec![
+                "local".to_string(),
+                "prod".to_string(),
+                "dev".to_string()
+            ])
+        );
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` secret config values
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A config value that is redacted from `Debug` and `Display` output.
+///
+/// Wrap sensitive fields in your environment config in `Secret` to keep them
+/// out of logs.  The value is read from and written to TOML unchanged, and is
+/// only available through `expose`.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::{Environment, Environments, Result, Secret};
+/// # use serde::{Deserialize, Serialize};
+/// #
+/// # fn foo() -> Result<()> {
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct RuntimeEnv {
+///     name: String,
+///     key: Option<Secret<String>>,
+/// }
+///
+/// let envs: Environments<Environment, RuntimeEnv> =
+///     "[envs.prod]\nname = \"Production\"\nkey = \"abcd-123-efg-45\"\n".parse()?;
+/// let prod = envs.current_from_value("prod")?;
+/// assert!(!format!("{envs:?}").contains("abcd"));
+/// assert_eq!(prod.key.as_ref().map(Secret::expose), Some(&"abcd-123-efg-45".to_string()));
+/// #   Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Copy, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Secret<T>(T);
+
+impl<T> Secret<T> {
+    /// Wrap a secret value.
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Get a reference to the secret value.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Consume the wrapper, returning the secret value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("***")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Secret;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Config {
+        key: Secret<String>,
+    }
+
+    #[test]
+    fn redacted() {
+        let config: Config = toml::from_str("key = \"abcd-123\"").expect("Unable to decode TOML");
+        assert_eq!(config.key.expose(), "abcd-123");
+        assert_eq!(format!("{config:?}"), "Config { key: \"***\" }");
+        assert_eq!(config.key.to_string(), "***");
+        assert_eq!(
+            toml::to_string(&config).ok().as_deref(),
+            Some("key = \"abcd-123\"\n")
+        );
+        assert_eq!(Secret::new(1).into_inner(), 1);
+    }
+}
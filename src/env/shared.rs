@@ -0,0 +1,184 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` environments shared between threads
+use crate::{
+    env::Environments,
+    error::Result,
+    hierarchy::{Hierarchy, Key},
+};
+use serde::de::DeserializeOwned;
+use std::{
+    convert::TryFrom,
+    path::Path,
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard},
+};
+
+/// `Environments` that can be shared between threads and reloaded in place.
+///
+/// Clones share the same environments, so a reload is seen by every clone.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::{Environment, Environments, Result, SharedEnvironments};
+/// # use serde::{Deserialize, Serialize};
+/// # use std::env;
+/// # use std::thread;
+/// #
+/// # fn foo() -> Result<()> {
+/// #[derive(Clone, Debug, Deserialize, Serialize)]
+/// struct RuntimeEnv {
+///     name: String,
+/// }
+///
+/// let envs: Environments<Environment, RuntimeEnv> =
+///     "[envs.dev]\nname = \"Development\"\n".parse()?;
+/// let shared = SharedEnvironments::new(envs);
+/// let worker = shared.clone();
+///
+/// env::set_var("env", "dev");
+/// let name = thread::spawn(move || worker.current().map(|env| env.name))
+///     .join()
+///     .expect("worker panicked")?;
+/// assert_eq!(name, "Development");
+/// #   Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct SharedEnvironments<S, T>
+where
+    S: Key,
+{
+    /// The shared environments
+    inner: Arc<RwLock<Environments<S, T>>>,
+}
+
+impl<S, T> Clone for SharedEnvironments<S, T>
+where
+    S: Key,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<S, T> From<Environments<S, T>> for SharedEnvironments<S, T>
+where
+    S: Key,
+{
+    fn from(envs: Environments<S, T>) -> Self {
+        Self::new(envs)
+    }
+}
+
+impl<S, T> SharedEnvironments<S, T>
+where
+    S: Key,
+{
+    /// Share the given environments.
+    #[must_use]
+    pub fn new(envs: Environments<S, T>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(envs)),
+        }
+    }
+
+    /// Lock the environments for reading.
+    pub fn read(&self) -> RwLockReadGuard<'_, Environments<S, T>> {
+        self.inner.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Replace the config for every environment, keeping the selection
+    /// variable.
+    pub fn replace(&self, envs: Environments<S, T>) {
+        self.inner
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(envs);
+    }
+}
+
+impl<S, T> SharedEnvironments<S, T>
+where
+    S: Key + TryFrom<String>,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    T: Clone,
+{
+    /// Get a copy of the current environment.  See `Environments::current`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn current(&self) -> Result<T> {
+        self.read().current().cloned()
+    }
+}
+
+impl<S, T> SharedEnvironments<S, T>
+where
+    T: DeserializeOwned,
+    S: Hierarchy,
+{
+    /// Reload the environments from a path.  The environments are left
+    /// unchanged if the path cannot be loaded.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn reload_from_path(&self, path: &Path) -> Result<()> {
+        let envs = Environments::from_path(path)?;
+        self.replace(envs);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SharedEnvironments;
+    use crate::env::{Environment, Environments};
+    use serde::{Deserialize, Serialize};
+    use std::{env, fs, thread};
+
+    #[derive(Clone, Debug, Deserialize, Serialize)]
+    struct RuntimeEnv {
+        name: String,
+    }
+
+    #[test]
+    fn reload_from_path() {
+        let envs: Environments<Environment, RuntimeEnv> = "[envs.prod]\nname = \"Production\"\n"
+            .parse()
+            .expect("Unable to parse Environments");
+        let shared = SharedEnvironments::new(envs);
+        let reader = shared.clone();
+
+        let path = env::temp_dir().join("tomlenv-shared.toml");
+        fs::write(&path, "[envs.prod]\nname = \"Reloaded\"\n").expect("Unable to write TOML");
+        shared
+            .reload_from_path(&path)
+            .expect("Unable to reload Environments");
+        fs::remove_file(&path).expect("Unable to remove TOML");
+
+        let name = thread::spawn(move || {
+            reader
+                .read()
+                .get(&Environment::Prod)
+                .map(|env| env.name.clone())
+        })
+        .join()
+        .expect("reader panicked");
+        assert_eq!(name.as_deref(), Some("Reloaded"));
+
+        assert!(shared.reload_from_path(&path).is_err());
+        assert_eq!(shared.read().len(), 1);
+    }
+}
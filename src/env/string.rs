@@ -0,0 +1,121 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` open-ended string environment hierarchy
+use serde::{Deserialize, Serialize};
+use std::{borrow::Borrow, fmt};
+
+/// An environment hierarchy keyed by arbitrary strings, for environment
+/// names that aren't known at compile time.
+///
+/// Every string is a valid environment, so conversion never fails.
+/// Environments are ordered by name.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::{Environments, Result, StringEnv};
+/// # use serde::{Deserialize, Serialize};
+/// #
+/// # fn foo() -> Result<()> {
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct RuntimeEnv {
+///     name: String,
+/// }
+///
+/// let envs: Environments<StringEnv, RuntimeEnv> =
+///     "[envs.eu-west-1]\nname = \"Ireland\"\n".parse()?;
+/// assert_eq!(envs.current_from_value("eu-west-1")?.name, "Ireland");
+/// #   Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[derive(Clone, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(transparent)]
+pub struct StringEnv(pub String);
+
+impl StringEnv {
+    /// The environment name.
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for StringEnv {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for StringEnv {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Display for StringEnv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<String> for StringEnv {
+    fn from(env: String) -> Self {
+        Self(env)
+    }
+}
+
+impl From<&str> for StringEnv {
+    fn from(env: &str) -> Self {
+        Self(env.to_string())
+    }
+}
+
+impl From<StringEnv> for String {
+    fn from(env: StringEnv) -> Self {
+        env.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StringEnv;
+    use crate::env::Environments;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct RuntimeEnv {
+        name: String,
+    }
+
+    #[test]
+    fn string_env() {
+        assert_eq!(
+            StringEnv::from(String::from("anything")).as_str(),
+            "anything"
+        );
+        assert!(StringEnv::from("a") < StringEnv::from("b"));
+        assert_eq!(StringEnv::from("canary").to_string(), "canary");
+
+        let envs: Environments<StringEnv, RuntimeEnv> =
+            "[envs.blue]\nname = \"Blue\"\n\n[envs.green]\nname = \"Green\"\n"
+                .parse()
+                .expect("Unable to parse Environments");
+        assert_eq!(
+            envs.get(&StringEnv::from("green")).map(|env| &env.name[..]),
+            Some("Green")
+        );
+        assert_eq!(
+            envs.current_from_value("blue").map(|env| &env.name[..]),
+            Ok("Blue")
+        );
+        let toml = envs.to_string().expect("Unable to serialize Environments");
+        assert!(toml.contains("[envs.blue]"));
+    }
+}
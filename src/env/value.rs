@@ -0,0 +1,526 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` TOML value helpers
+use crate::{
+    env::VarReader,
+    error::{Error, Result},
+};
+use serde_ignored::Path;
+use std::collections::{BTreeMap, BTreeSet};
+use toml::{value::Table, Value};
+
+/// Merge the top-level `defaults` table, if present, under every table in
+/// `envs`.  Values already present in an environment take precedence.
+#[track_caller]
+pub(crate) fn apply_defaults(document: &mut Value) -> Result<()> {
+    let defaults = match document.get("defaults") {
+        Some(Value::Table(defaults)) => defaults.clone(),
+        Some(_) => return Err(Error::invalid_table("defaults")),
+        None => return Ok(()),
+    };
+
+    if let Some(Value::Table(envs)) = document.get_mut("envs") {
+        for (_, env) in envs.iter_mut() {
+            if let Value::Table(env) = env {
+                merge_tables(env, &defaults);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Substitute `${NAME}` and `$NAME` references in every string value of
+/// `document` with the values read from `vars`.  `$$` is replaced with a
+/// literal `$`.  Keys and comments are never expanded.
+#[track_caller]
+pub(crate) fn expand_vars<R>(document: &mut Value, vars: &R) -> Result<()>
+where
+    R: VarReader,
+{
+    match document {
+        Value::String(text) => *text = expand_str(text, vars)?,
+        Value::Array(values) => {
+            for value in values {
+                expand_vars(value, vars)?;
+            }
+        }
+        Value::Table(table) => {
+            for (_, value) in table.iter_mut() {
+                expand_vars(value, vars)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Expand the variable references in a single string.  A bare `$NAME` must
+/// start with a letter or `_`, so `$5` is left as is.
+#[track_caller]
+fn expand_str<R>(text: &str, vars: &R) -> Result<String>
+where
+    R: VarReader,
+{
+    let mut expanded = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            expanded.push(c);
+            continue;
+        }
+
+        let mut name = String::new();
+        match chars.peek() {
+            Some('$') => {
+                let _dollar = chars.next();
+                expanded.push('$');
+                continue;
+            }
+            Some('{') => {
+                let _brace = chars.next();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(Error::invalid_expansion(&name)),
+                    }
+                }
+            }
+            Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        name.push(c);
+                        let _c = chars.next();
+                    } else {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        if name.is_empty() {
+            expanded.push('$');
+        } else {
+            let value = match vars.var(&name) {
+                Ok(value) => value,
+                Err(e) => return Err(Error::var(&name, e)),
+            };
+            expanded.push_str(&value);
+        }
+    }
+    Ok(expanded)
+}
+
+/// Move the top-level `from` table, if present, to `to`.
+pub(crate) fn rename_table(document: &mut Value, from: &str, to: &str) {
+    if from == to {
+        return;
+    }
+    if let Value::Table(document) = document {
+        if let Some(table) = document.remove(from) {
+            let _prev = document.insert(to.to_string(), table);
+        }
+    }
+}
+
+/// Merge each table in `envs` that has an `inherits` key over the table it
+/// names, following the chain of `inherits` keys.  The `inherits` keys are
+/// removed.
+#[track_caller]
+pub(crate) fn apply_inherits(document: &mut Value) -> Result<()> {
+    let Some(Value::Table(envs)) = document.get_mut("envs") else {
+        return Ok(());
+    };
+
+    let mut resolved = BTreeMap::new();
+    for (name, env) in envs.iter() {
+        if let Value::Table(_) = env {
+            let _table = resolve_inherits(envs, name, &mut resolved, &mut Vec::new())?;
+        }
+    }
+    for (name, table) in resolved {
+        let _prev = envs.insert(name, Value::Table(table));
+    }
+    Ok(())
+}
+
+/// Resolve the table for the named environment, merged over the tables it
+/// inherits from.  `chain` holds the environments currently being resolved.
+#[track_caller]
+fn resolve_inherits(
+    envs: &Table,
+    name: &str,
+    resolved: &mut BTreeMap<String, Table>,
+    chain: &mut Vec<String>,
+) -> Result<Table> {
+    if let Some(table) = resolved.get(name) {
+        return Ok(table.clone());
+    }
+    if chain.iter().any(|link| link == name) {
+        chain.push(name.to_string());
+        return Err(Error::inheritance_cycle(chain));
+    }
+
+    let mut table = match envs.get(name) {
+        Some(Value::Table(table)) => table.clone(),
+        _ => {
+            return Err(Error::invalid_inherits(
+                chain.last().map_or(name, String::as_str),
+            ))
+        }
+    };
+    if let Some(parent) = table.remove("inherits") {
+        let Value::String(parent) = parent else {
+            return Err(Error::invalid_inherits(name));
+        };
+        chain.push(name.to_string());
+        let parent = resolve_inherits(envs, &parent, resolved, chain)?;
+        let _link = chain.pop();
+        merge_tables(&mut table, &parent);
+    }
+    let _prev = resolved.insert(name.to_string(), table.clone());
+    Ok(table)
+}
+
+/// Apply `{PREFIX}_{ENV}_{FIELD}` overrides from the given variables to the
+/// matching top-level fields of each table in `envs`.  The environment and
+/// field names are matched case-insensitively.  An override for an existing
+/// integer, float, or boolean field is parsed as that type, otherwise it is
+/// applied as a string.
+#[track_caller]
+pub(crate) fn apply_env_overrides<I>(document: &mut Value, prefix: &str, vars: I) -> Result<()>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let prefix = format!("{}_", prefix.to_uppercase());
+    let Some(Value::Table(envs)) = document.get_mut("envs") else {
+        return Ok(());
+    };
+
+    for (var, value) in vars {
+        let upper = var.to_uppercase();
+        let Some(rest) = upper.strip_prefix(&prefix) else {
+            continue;
+        };
+        for (env_name, env) in envs.iter_mut() {
+            let field = match rest.strip_prefix(&format!("{}_", env_name.to_uppercase())) {
+                Some(field) if !field.is_empty() => field,
+                _ => continue,
+            };
+            if let Value::Table(env) = env {
+                let key = env
+                    .keys()
+                    .find(|key| key.to_uppercase() == field)
+                    .cloned()
+                    .unwrap_or_else(|| field.to_lowercase());
+                let new_value = match env.get(&key) {
+                    Some(Value::Integer(_)) => value.parse().ok().map(Value::Integer),
+                    Some(Value::Float(_)) => value.parse().ok().map(Value::Float),
+                    Some(Value::Boolean(_)) => value.parse().ok().map(Value::Boolean),
+                    _ => Some(Value::String(value.clone())),
+                };
+                let Some(new_value) = new_value else {
+                    return Err(Error::invalid_override(&var));
+                };
+                let _prev = env.insert(key, new_value);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Deep merge `parent` under `child`, keeping any values already in `child`.
+pub(crate) fn merge_tables(child: &mut Table, parent: &Table) {
+    for (key, parent_value) in parent {
+        match (child.get_mut(key), parent_value) {
+            (Some(Value::Table(child_table)), Value::Table(parent_table)) => {
+                merge_tables(child_table, parent_table);
+            }
+            (Some(_), _) => {}
+            (None, _) => {
+                let _prev = child.insert(key.clone(), parent_value.clone());
+            }
+        }
+    }
+}
+
+/// The keys along a path serde ignored, skipping `Option` and newtype
+/// wrappers, e.g. `["envs", "prod", "nmae"]`.
+pub(crate) fn ignored_keys(path: &Path<'_>) -> Vec<String> {
+    let mut keys = match path {
+        Path::Root => return Vec::new(),
+        Path::Seq { parent, .. }
+        | Path::Map { parent, .. }
+        | Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => ignored_keys(parent),
+    };
+    match path {
+        Path::Seq { index, .. } => keys.push(index.to_string()),
+        Path::Map { key, .. } => keys.push(key.clone()),
+        _ => {}
+    }
+    keys
+}
+
+/// Map the keys serde ignored while deserializing the environments back to
+/// where they were written in `declared`, the document before any `inherits`
+/// or `defaults` were merged.  A key merged in from `defaults` is reported
+/// under `defaults`, and one merged in from another environment is reported
+/// under that environment only.  The paths are sorted and deduplicated.
+pub(crate) fn unknown_fields(declared: &Value, ignored: &[Vec<String>]) -> Vec<String> {
+    let mut unknown = BTreeSet::new();
+    for keys in ignored {
+        let path = match keys.as_slice() {
+            [envs, _, _, ..] if envs == "envs" && contains(declared, keys) => keys.join("."),
+            [envs, _, rest @ ..] if envs == "envs" => match declared.get("defaults") {
+                Some(defaults) if contains(defaults, rest) => {
+                    format!("defaults.{}", rest.join("."))
+                }
+                _ => continue,
+            },
+            _ => continue,
+        };
+        let _new = unknown.insert(path);
+    }
+    unknown.into_iter().collect()
+}
+
+/// Does `value` have a value at the given keys?
+fn contains(value: &Value, keys: &[String]) -> bool {
+    let mut value = value;
+    for key in keys {
+        let next = match value {
+            Value::Table(table) => table.get(key),
+            Value::Array(values) => key
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| values.get(index)),
+            _ => None,
+        };
+        match next {
+            Some(next) => value = next,
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        apply_defaults, apply_env_overrides, apply_inherits, expand_vars, merge_tables,
+        rename_table, unknown_fields,
+    };
+    use std::collections::HashMap;
+    use toml::Value;
+
+    #[test]
+    fn merge() {
+        let mut child: Value = toml::from_str(
+            r#"name = "child"
+[nested]
+a = 1
+"#,
+        )
+        .expect("valid toml");
+        let parent: Value = toml::from_str(
+            r#"name = "parent"
+key = "parent-key"
+[nested]
+a = 2
+b = 3
+"#,
+        )
+        .expect("valid toml");
+
+        if let (Value::Table(child), Value::Table(parent)) = (&mut child, &parent) {
+            merge_tables(child, parent);
+        }
+        assert_eq!(child["name"].as_str(), Some("child"));
+        assert_eq!(child["key"].as_str(), Some("parent-key"));
+        assert_eq!(child["nested"]["a"].as_integer(), Some(1));
+        assert_eq!(child["nested"]["b"].as_integer(), Some(3));
+    }
+
+    #[test]
+    fn env_overrides() {
+        let mut document: Value = toml::from_str(
+            r#"[envs.prod]
+name = "Production"
+port = 80
+
+[envs.dev]
+name = "Development"
+"#,
+        )
+        .expect("valid toml");
+        let vars = vec![
+            ("APP_PROD_PORT".to_string(), "443".to_string()),
+            ("APP_PROD_KEY".to_string(), "secret".to_string()),
+            ("APP_DEV_NAME".to_string(), "Dev Override".to_string()),
+            ("OTHER_DEV_NAME".to_string(), "Ignored".to_string()),
+        ];
+        apply_env_overrides(&mut document, "app", vars).expect("valid overrides");
+        assert_eq!(document["envs"]["prod"]["port"].as_integer(), Some(443));
+        assert_eq!(document["envs"]["prod"]["key"].as_str(), Some("secret"));
+        assert_eq!(
+            document["envs"]["prod"]["name"].as_str(),
+            Some("Production")
+        );
+        assert_eq!(
+            document["envs"]["dev"]["name"].as_str(),
+            Some("Dev Override")
+        );
+
+        let invalid = vec![("APP_PROD_PORT".to_string(), "https".to_string())];
+        assert!(apply_env_overrides(&mut document, "APP", invalid).is_err());
+    }
+
+    #[test]
+    fn defaults_not_a_table() {
+        let mut document: Value = toml::from_str("defaults = 1\n").expect("valid toml");
+        assert!(apply_defaults(&mut document).is_err());
+    }
+
+    #[test]
+    fn unknown() {
+        let declared: Value = toml::from_str(
+            r#"[defaults]
+port = 80
+prot = 80
+
+[envs.base]
+name = "Base"
+nmae = "Typo"
+
+[envs.prod]
+name = "Production"
+inherits = "base"
+[envs.prod.nested]
+a = 1
+b = 2
+"#,
+        )
+        .expect("valid toml");
+        let ignored: Vec<Vec<String>> = [
+            "envs.prod.nested.b",
+            "envs.prod.nmae",
+            "envs.base.nmae",
+            "envs.base.prot",
+            "envs.prod.prot",
+            "defaults",
+        ]
+        .iter()
+        .map(|path| path.split('.').map(str::to_string).collect())
+        .collect();
+        assert_eq!(
+            unknown_fields(&declared, &ignored),
+            vec![
+                "defaults.prot".to_string(),
+                "envs.base.nmae".to_string(),
+                "envs.prod.nested.b".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn rename() {
+        let mut document: Value = toml::from_str(
+            r#"[environments.prod]
+name = "Production"
+"#,
+        )
+        .expect("valid toml");
+        rename_table(&mut document, "environments", "envs");
+        assert!(document.get("environments").is_none());
+        assert_eq!(
+            document["envs"]["prod"]["name"].as_str(),
+            Some("Production")
+        );
+    }
+
+    #[test]
+    fn expand() {
+        let mut vars = HashMap::new();
+        let _prev = vars.insert("SECRET_KEY".to_string(), "abcd-123".to_string());
+        let _prev = vars.insert("NAME".to_string(), "Production".to_string());
+        let _prev = vars.insert("QUOTED".to_string(), "a \"b\" \\c\nd".to_string());
+        let mut document: Value = toml::from_str(
+            r#"# costs $5, ${NOT_SET}
+key = "${SECRET_KEY}"
+name = "$NAME env"
+cost = "$$5 $5 $"
+quoted = ["$QUOTED"]
+"#,
+        )
+        .expect("valid toml");
+        assert!(expand_vars(&mut document, &vars).is_ok());
+        assert_eq!(document["key"].as_str(), Some("abcd-123"));
+        assert_eq!(document["name"].as_str(), Some("Production env"));
+        assert_eq!(document["cost"].as_str(), Some("$5 $5 $"));
+        assert_eq!(document["quoted"][0].as_str(), Some("a \"b\" \\c\nd"));
+
+        let mut missing = Value::String("${MISSING}".to_string());
+        assert!(expand_vars(&mut missing, &vars).is_err());
+        let mut unclosed = Value::String("${SECRET_KEY".to_string());
+        assert!(expand_vars(&mut unclosed, &vars).is_err());
+    }
+
+    #[test]
+    fn inherits() {
+        let mut document: Value = toml::from_str(
+            r#"[envs.prod]
+name = "Production"
+key = "prod-key"
+port = 443
+
+[envs.stage]
+inherits = "prod"
+name = "Stage"
+
+[envs.dev]
+inherits = "stage"
+port = 8080
+"#,
+        )
+        .expect("valid toml");
+        apply_inherits(&mut document).expect("valid inherits");
+        assert_eq!(document["envs"]["stage"]["name"].as_str(), Some("Stage"));
+        assert_eq!(document["envs"]["stage"]["key"].as_str(), Some("prod-key"));
+        assert_eq!(document["envs"]["dev"]["name"].as_str(), Some("Stage"));
+        assert_eq!(document["envs"]["dev"]["port"].as_integer(), Some(8080));
+        assert!(document["envs"]["dev"].get("inherits").is_none());
+    }
+
+    #[test]
+    fn inherits_invalid() {
+        let mut cycle: Value = toml::from_str(
+            r#"[envs.dev]
+inherits = "test"
+
+[envs.test]
+inherits = "dev"
+"#,
+        )
+        .expect("valid toml");
+        let err = apply_inherits(&mut cycle).expect_err("dev and test inherit from each other");
+        assert_eq!(err.reason(), "inheritance cycle: dev -> test -> dev");
+
+        let mut unknown: Value =
+            toml::from_str("[envs.dev]\ninherits = \"prod\"\n").expect("valid toml");
+        let err = apply_inherits(&mut unknown).expect_err("prod is not an environment");
+        assert_eq!(
+            err.reason(),
+            "'inherits' in 'dev' must name another environment"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` environment variable readers
+use std::{
+    collections::{BTreeMap, HashMap},
+    env::{self, VarError},
+    hash::BuildHasher,
+};
+
+/// Read the variable used to select the current environment.
+///
+/// The default implementation, `StdVarReader`, reads from the process
+/// environment.  A map of names to values can be used in its place, which
+/// avoids mutating global process state with `env::set_var` in tests.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::VarReader;
+/// # use std::collections::HashMap;
+/// let mut vars = HashMap::new();
+/// let _ = vars.insert("env".to_string(), "dev".to_string());
+/// assert_eq!(vars.var("env"), Ok("dev".to_string()));
+/// assert!(vars.var("other").is_err());
+/// ```
+pub trait VarReader {
+    /// Read the value of the given variable.
+    ///
+    /// # Errors
+    ///
+    fn var(&self, name: &str) -> Result<String, VarError>;
+}
+
+/// Read variables from the process environment via `std::env::var`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdVarReader;
+
+impl VarReader for StdVarReader {
+    fn var(&self, name: &str) -> Result<String, VarError> {
+        env::var(name)
+    }
+}
+
+impl<H> VarReader for HashMap<String, String, H>
+where
+    H: BuildHasher,
+{
+    fn var(&self, name: &str) -> Result<String, VarError> {
+        self.get(name).cloned().ok_or(VarError::NotPresent)
+    }
+}
+
+impl VarReader for BTreeMap<String, String> {
+    fn var(&self, name: &str) -> Result<String, VarError> {
+        self.get(name).cloned().ok_or(VarError::NotPresent)
+    }
+}
+
+impl<R> VarReader for &R
+where
+    R: VarReader + ?Sized,
+{
+    fn var(&self, name: &str) -> Result<String, VarError> {
+        (**self).var(name)
+    }
+}
+
+/// The variables in the process environment, skipping any name or value that
+/// is not valid unicode
+pub(crate) fn process_vars() -> impl Iterator<Item = (String, String)> {
+    env::vars_os()
+        .filter_map(|(var, value)| Some((var.into_string().ok()?, value.into_string().ok()?)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{StdVarReader, VarReader};
+    use std::{collections::BTreeMap, env, env::VarError};
+
+    #[test]
+    fn std_reader() {
+        env::set_var("TOMLENV_STD_READER", "prod");
+        assert_eq!(
+            StdVarReader.var("TOMLENV_STD_READER"),
+            Ok("prod".to_string())
+        );
+        assert_eq!(
+            StdVarReader.var("TOMLENV_STD_READER_MISSING"),
+            Err(VarError::NotPresent)
+        );
+    }
+
+    #[test]
+    fn map_reader() {
+        let mut vars = BTreeMap::new();
+        let _prev = vars.insert("env".to_string(), "stage".to_string());
+        assert_eq!(vars.var("env"), Ok("stage".to_string()));
+        assert_eq!(vars.var("missing"), Err(VarError::NotPresent));
+    }
+}
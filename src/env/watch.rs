@@ -0,0 +1,297 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` hot reload
+use crate::{
+    env::{environments::read_path, Environments, SharedEnvironments},
+    error::{Error, Result},
+    hierarchy::{Hierarchy, Key},
+};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Arc, Mutex, PoisonError,
+    },
+    thread,
+    time::Duration,
+};
+
+/// The default quiet period after a change before the file is reloaded
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watch a TOML file and reload the environments whenever it changes.
+///
+/// Rapid successive writes are debounced into a single reload.  If a reload
+/// fails, the previous environments are kept and the error is available from
+/// `take_error`.  Each failed reload is also passed to an error handler, which
+/// by default logs it as a warning with the `tracing` feature, or to stderr
+/// without it.  Use `with_on_error` to handle failures yourself.  Watching
+/// stops when the watcher is dropped.
+///
+/// # Example
+///
+/// ```no_run
+/// # use tomlenv::{Environment, EnvironmentsWatcher, Result};
+/// # use serde::{Deserialize, Serialize};
+/// # use std::path::Path;
+/// #
+/// # fn foo() -> Result<()> {
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct RuntimeEnv {
+///     name: String,
+/// }
+///
+/// let watcher: EnvironmentsWatcher<Environment, RuntimeEnv> =
+///     EnvironmentsWatcher::new(Path::new("env.toml"))?;
+/// let envs = watcher.environments();
+/// let name = envs.read().current()?.name.clone();
+/// #   Ok(())
+/// # }
+/// ```
+pub struct EnvironmentsWatcher<S, T>
+where
+    S: Key,
+{
+    /// The most recently loaded environments
+    envs: SharedEnvironments<S, T>,
+    /// The most recent reload failure
+    error: Arc<Mutex<Option<Error>>>,
+    /// The file system watcher, watching until dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl<S, T> fmt::Debug for EnvironmentsWatcher<S, T>
+where
+    S: Key + fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvironmentsWatcher")
+            .field("envs", &self.envs)
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, T> EnvironmentsWatcher<S, T>
+where
+    T: DeserializeOwned + Send + Sync + 'static,
+    S: Hierarchy + Send + Sync + 'static,
+{
+    /// Load the environments from the given path and watch it for changes.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn new(path: &Path) -> Result<Self> {
+        Self::with_debounce(path, DEFAULT_DEBOUNCE)
+    }
+
+    /// Load the environments from the given path and watch it for changes,
+    /// reloading once no further changes have been seen for `debounce`.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn with_debounce(path: &Path, debounce: Duration) -> Result<Self> {
+        Self::with_on_error(path, debounce, log_error)
+    }
+
+    /// Load the environments from the given path and watch it for changes,
+    /// as `with_debounce` does, passing the path and error of each failed
+    /// reload to `on_error` in place of the default logging.
+    ///
+    /// # Errors
+    ///
+    #[track_caller]
+    pub fn with_on_error<F>(path: &Path, debounce: Duration, mut on_error: F) -> Result<Self>
+    where
+        F: FnMut(&Path, &Error) + Send + 'static,
+    {
+        let envs = SharedEnvironments::new(Environments::from_path(path)?);
+        let error = Arc::new(Mutex::new(None));
+
+        // Watch the parent directory, as editors often replace the file
+        // rather than writing to it.
+        let path = path.to_path_buf();
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let (tx, rx) = channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _sent = tx.send(event);
+        })?;
+        watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+        let reload_envs = envs.clone();
+        let reload_error = Arc::clone(&error);
+        let _handle = thread::spawn(move || {
+            while wait_for_change(&rx, &path, debounce) {
+                match read_path(&path).and_then(|toml| Environments::from_str(&toml)) {
+                    Ok(reloaded) => reload_envs.replace(reloaded),
+                    Err(e) => {
+                        on_error(&path, &e);
+                        *reload_error.lock().unwrap_or_else(PoisonError::into_inner) = Some(e);
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            envs,
+            error,
+            _watcher: watcher,
+        })
+    }
+
+    /// The most recently loaded environments.
+    #[must_use]
+    pub fn environments(&self) -> SharedEnvironments<S, T> {
+        self.envs.clone()
+    }
+
+    /// Take the error from the most recent failed reload, if any.
+    #[must_use]
+    pub fn take_error(&self) -> Option<Error> {
+        self.error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+}
+
+/// Log a failed reload, the default error handler
+fn log_error(path: &Path, error: &Error) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(path = %path.display(), %error, "failed to reload environments");
+    #[cfg(not(feature = "tracing"))]
+    eprintln!(
+        "failed to reload environments from '{}': {error}",
+        path.display()
+    );
+}
+
+/// Block until the file at `path` changes and then stays unchanged for
+/// `debounce`.  Returns `false` once the watcher has been dropped.
+fn wait_for_change(rx: &Receiver<notify::Result<Event>>, path: &Path, debounce: Duration) -> bool {
+    let is_change = |event: &notify::Result<Event>| match event {
+        Ok(event) => event
+            .paths
+            .iter()
+            .any(|changed| changed.file_name() == path.file_name()),
+        Err(_) => false,
+    };
+
+    loop {
+        match rx.recv() {
+            Ok(event) if is_change(&event) => break,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+    }
+    loop {
+        match rx.recv_timeout(debounce) {
+            Ok(_) => {}
+            Err(RecvTimeoutError::Timeout) => return true,
+            Err(RecvTimeoutError::Disconnected) => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::EnvironmentsWatcher;
+    use crate::env::Environment;
+    use serde::{Deserialize, Serialize};
+    use std::{
+        env, fs,
+        sync::mpsc::channel,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct RuntimeEnv {
+        name: String,
+    }
+
+    fn wait_until<F>(mut done: F) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if done() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn reload() {
+        let dir = env::temp_dir().join("tomlenv-watch");
+        fs::create_dir_all(&dir).expect("Unable to create watch dir");
+        let path = dir.join("env.toml");
+        fs::write(&path, "[envs.prod]\nname = \"Production\"\n").expect("Unable to write TOML");
+
+        let watcher: EnvironmentsWatcher<Environment, RuntimeEnv> =
+            EnvironmentsWatcher::with_debounce(&path, Duration::from_millis(50))
+                .expect("Unable to watch TOML");
+        let envs = watcher.environments();
+        let prod_name = || {
+            envs.read()
+                .get(&Environment::Prod)
+                .map(|env| env.name.clone())
+        };
+        assert_eq!(prod_name().as_deref(), Some("Production"));
+
+        fs::write(&path, "[envs.prod]\nname = \"Reloaded\"\n").expect("Unable to write TOML");
+        assert!(wait_until(|| prod_name().as_deref() == Some("Reloaded")));
+
+        fs::write(&path, "[envs.prod\n").expect("Unable to write TOML");
+        let mut error = None;
+        assert!(wait_until(|| {
+            error = watcher.take_error();
+            error.is_some()
+        }));
+        assert_eq!(prod_name().as_deref(), Some("Reloaded"));
+
+        fs::remove_dir_all(&dir).expect("Unable to remove watch dir");
+    }
+
+    #[test]
+    fn reload_on_error() {
+        let dir = env::temp_dir().join("tomlenv-watch-on-error");
+        fs::create_dir_all(&dir).expect("Unable to create watch dir");
+        let path = dir.join("env.toml");
+        fs::write(&path, "[envs.prod]\nname = \"Production\"\n").expect("Unable to write TOML");
+
+        let (tx, rx) = channel();
+        let watcher: EnvironmentsWatcher<Environment, RuntimeEnv> =
+            EnvironmentsWatcher::with_on_error(&path, Duration::from_millis(50), move |path, e| {
+                let _sent = tx.send((path.to_path_buf(), e.to_string()));
+            })
+            .expect("Unable to watch TOML");
+
+        fs::write(&path, "[envs.prod\n").expect("Unable to write TOML");
+        let (failed, _) = rx
+            .recv_timeout(Duration::from_secs(5))
+            .expect("The failed reload was not handled");
+        assert_eq!(failed, path);
+        assert!(watcher.take_error().is_some());
+
+        fs::remove_dir_all(&dir).expect("Unable to remove watch dir");
+    }
+}
@@ -9,9 +9,10 @@
 //! Error Codes
 use std::fmt;
 
-/// Error Codes
-#[derive(Copy, Clone, Debug)]
-pub(crate) enum ErrCode {
+/// The category of an `Error`
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ErrCode {
     /// An error caused by the client
     Client,
     /// An environmental error
@@ -32,6 +33,41 @@ pub(crate) enum ErrCode {
     Unknown,
 }
 
+impl ErrCode {
+    /// A stable number for this code, from `Client` (1) to `Unknown` (9).
+    #[must_use]
+    pub const fn number(self) -> i32 {
+        match self {
+            Self::Client => 1,
+            Self::Env => 2,
+            Self::Framework => 3,
+            Self::HttpClient => 4,
+            Self::Io => 5,
+            Self::Parse => 6,
+            Self::Server => 7,
+            Self::Unauthorized => 8,
+            Self::Unknown => 9,
+        }
+    }
+
+    /// A process exit code for this code, following the BSD `sysexits.h`
+    /// conventions, e.g. `EX_CONFIG` (78) for `Env` and `EX_IOERR` (74) for
+    /// `Io`.
+    #[must_use]
+    pub const fn exit_code(self) -> i32 {
+        match self {
+            Self::Client => 64,
+            Self::Parse => 65,
+            Self::HttpClient => 69,
+            Self::Framework | Self::Server => 70,
+            Self::Io => 74,
+            Self::Unauthorized => 77,
+            Self::Env => 78,
+            Self::Unknown => 1,
+        }
+    }
+}
+
 impl fmt::Display for ErrCode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
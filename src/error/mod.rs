@@ -10,48 +10,98 @@
 mod codes;
 mod sources;
 
-pub(crate) use codes::ErrCode;
+pub use codes::ErrCode;
 use getset::Getters;
-pub(crate) use sources::ErrSource;
-use std::fmt;
+pub use sources::ErrSource;
+use std::{
+    backtrace::Backtrace,
+    env::VarError,
+    io,
+    panic::Location,
+    path::{Path, PathBuf},
+};
 
 /// A result that must include an `tomlenv::Error`
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// The category of an `Error`, e.g. to retry on `Io` but not on `Parse`
+pub type ErrorKind = ErrCode;
+
 /// An error from the library
-#[derive(Debug, Getters)]
+#[derive(Debug, Getters, thiserror::Error)]
 #[getset(get = "pub(crate)")]
-#[allow(dead_code)]
+#[error("{code}: {reason}{}", fmt_source(.source.as_ref()))]
 pub struct Error {
     /// the code
+    #[getset(get = "pub")]
     code: ErrCode,
     /// the reason
     reason: String,
-    /// the description
-    description: String,
     /// the source
+    #[source]
     source: Option<ErrSource>,
+    /// where the error was created, if `RUST_BACKTRACE` is set
+    #[getset(skip)]
+    backtrace: Box<Backtrace>,
+    /// the call site that produced the error
+    #[getset(skip)]
+    caller: &'static Location<'static>,
 }
 
 impl Error {
+    #[track_caller]
     pub(crate) fn new<U>(code: ErrCode, reason: U, source: Option<ErrSource>) -> Self
     where
         U: Into<String>,
     {
-        let reason = reason.into();
-        let code_str: &str = code.into();
-        let description = format!("{code_str}: {reason}");
-
         Self {
             code,
-            reason,
-            description,
+            reason: reason.into(),
             source,
+            backtrace: Box::new(Backtrace::capture()),
+            caller: Location::caller(),
         }
     }
 
+    pub(crate) fn with_source(mut self, source: ErrSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// The category of this error
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        self.code
+    }
+
+    /// The backtrace of where this error was created.  This is only
+    /// captured if the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment
+    /// variable is set.
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+
+    /// The location of the call into this crate that produced this error.
+    #[must_use]
+    pub fn caller(&self) -> &'static Location<'static> {
+        self.caller
+    }
+
+    /// The stable number of this error's code.  See `ErrCode::number`.
+    #[must_use]
+    pub fn code_number(&self) -> i32 {
+        self.code.number()
+    }
+
+    /// A process exit code for this error.  See `ErrCode::exit_code`.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        self.code.exit_code()
+    }
+
     /// Generate an invalid runtime environment error
     #[must_use]
+    #[track_caller]
     pub fn invalid_runtime_environment(env: &str) -> Self {
         Self::new(
             ErrCode::Env,
@@ -60,56 +110,286 @@ impl Error {
         )
     }
 
-    pub(crate) fn invalid_current_environment(var: &str) -> Self {
+    #[track_caller]
+    pub(crate) fn var(var: &str, source: VarError) -> Self {
         Self::new(
             ErrCode::Env,
-            format!("invalid current environment '{var}'"),
+            format!("unable to read environment variable '{var}'"),
+            Some(ErrSource::Var(source)),
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn read_path(path: &Path, source: io::Error) -> Self {
+        Self::new(
+            ErrCode::Io,
+            format!("unable to read '{}'", path.display()),
+            Some(ErrSource::Io(source)),
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn write_path(path: &Path, source: io::Error) -> Self {
+        Self::new(
+            ErrCode::Io,
+            format!("unable to write '{}'", path.display()),
+            Some(ErrSource::Io(source)),
+        )
+    }
+
+    #[cfg(feature = "http")]
+    #[track_caller]
+    pub(crate) fn fetch_url(url: &str, source: ureq::Error) -> Self {
+        let reason = match &source {
+            ureq::Error::Status(status, _) => format!("unable to fetch '{url}': status {status}"),
+            ureq::Error::Transport(_) => format!("unable to fetch '{url}'"),
+        };
+        Self::new(
+            ErrCode::HttpClient,
+            reason,
+            Some(ErrSource::Http(Box::new(source))),
+        )
+    }
+
+    #[cfg(feature = "http")]
+    #[track_caller]
+    pub(crate) fn read_url(url: &str, source: io::Error) -> Self {
+        Self::new(
+            ErrCode::HttpClient,
+            format!("unable to read the response from '{url}'"),
+            Some(ErrSource::Io(source)),
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn not_found_in_ancestors(file_name: &str, searched: &[PathBuf]) -> Self {
+        let searched: Vec<String> = searched
+            .iter()
+            .map(|dir| format!("'{}'", dir.display()))
+            .collect();
+        Self::new(
+            ErrCode::Io,
+            format!("unable to find '{file_name}' in {}", searched.join(", ")),
             None,
         )
     }
-}
 
-impl std::error::Error for Error {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        if let Some(ref x) = self.source {
-            Some(x)
-        } else {
-            None
-        }
+    #[track_caller]
+    pub(crate) fn invalid_expansion(name: &str) -> Self {
+        Self::new(
+            ErrCode::Parse,
+            format!("unterminated variable reference '${{{name}'"),
+            None,
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_inherits(env: &str) -> Self {
+        Self::new(
+            ErrCode::Parse,
+            format!("'inherits' in '{env}' must name another environment"),
+            None,
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn inheritance_cycle(chain: &[String]) -> Self {
+        Self::new(
+            ErrCode::Parse,
+            format!("inheritance cycle: {}", chain.join(" -> ")),
+            None,
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_override(var: &str) -> Self {
+        Self::new(
+            ErrCode::Env,
+            format!("invalid override value in '{var}'"),
+            None,
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn no_environments() -> Self {
+        Self::new(ErrCode::Parse, "no environments defined", None)
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_table(key: &str) -> Self {
+        Self::new(ErrCode::Parse, format!("'{key}' must be a table"), None)
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_current_environment(var: &str, value: &str) -> Self {
+        Self::new(
+            ErrCode::Env,
+            format!("invalid current environment '{value}' from '{var}'"),
+            None,
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_selected_environment() -> Self {
+        Self::new(ErrCode::Env, "the selected environment has no config", None)
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_environment(value: &str) -> Self {
+        Self::new(ErrCode::Env, format!("invalid environment '{value}'"), None)
+    }
+
+    #[track_caller]
+    pub(crate) fn missing_environments(missing: &[String]) -> Self {
+        Self::new(
+            ErrCode::Env,
+            format!("missing environments: {}", missing.join(", ")),
+            None,
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn unknown_fields(unknown: &[String]) -> Self {
+        Self::new(
+            ErrCode::Parse,
+            format!("unknown fields: {}", unknown.join(", ")),
+            None,
+        )
+    }
+
+    #[track_caller]
+    pub(crate) fn invalid_default_environment() -> Self {
+        Self::new(ErrCode::Env, "invalid default environment", None)
     }
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.description)
+/// Format the source, if any, as a suffix for the description
+fn fmt_source(source: Option<&ErrSource>) -> String {
+    source.map_or_else(String::new, |source| format!(": {source}"))
+}
+
+/// Errors are equal if their codes and reasons are equal, regardless of source.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code && self.reason == other.reason
     }
 }
 
+impl Eq for Error {}
+
 impl From<&str> for Error {
+    #[track_caller]
     fn from(text: &str) -> Self {
-        let split = text.split(':');
-        let vec = split.collect::<Vec<&str>>();
-        let code = vec.first().unwrap_or(&"");
-        let reason = vec.get(1).unwrap_or(&"");
-        Self::new((*code).into(), *reason, None)
+        let mut split = text.splitn(2, ':');
+        let code = split.next().unwrap_or("");
+        let reason = split.next().unwrap_or("").trim_start();
+        Self::new(code.into(), reason, None)
     }
 }
 
 impl From<String> for Error {
+    #[track_caller]
     fn from(text: String) -> Self {
-        let split = text.split(':');
-        let vec = split.collect::<Vec<&str>>();
-        let code = vec.first().unwrap_or(&"");
-        let reason = vec.get(1).unwrap_or(&"");
-        Self::new((*code).into(), *reason, None)
+        Self::from(&text[..])
     }
 }
 
-// impl<S> From<<S as TryFrom<String>>::Error> for Error
-// where
-//     S: DeserializeOwned + Serialize + Ord + PartialOrd + TryFrom<String>,
-// {
-//     fn from(_s: S) -> Self {
-//         Self::new(ErrCode::Env, "", None)
-//     }
-// }
+#[cfg(test)]
+mod test {
+    use super::{ErrCode, ErrSource, Error, ErrorKind};
+    use std::{backtrace::BacktraceStatus, env::VarError, ffi::OsString};
+
+    #[test]
+    fn from_str() {
+        let err = Error::from("env:bad value: see docs");
+        assert_eq!(err.reason(), "bad value: see docs");
+        assert_eq!(err.to_string(), "env: bad value: see docs");
+
+        let round_trip = Error::from(err.to_string());
+        assert_eq!(round_trip.reason(), "bad value: see docs");
+        assert_eq!(round_trip.to_string(), err.to_string());
+
+        let no_reason = Error::from("parse");
+        assert_eq!(no_reason.reason(), "");
+    }
+
+    #[test]
+    fn code_and_source() {
+        let err = Error::from(toml::from_str::<toml::Value>("=").expect_err("invalid toml"));
+        assert!(matches!(err.code(), ErrCode::Parse));
+        assert_eq!(err.kind(), ErrorKind::Parse);
+        assert!(matches!(err.source(), Some(ErrSource::TomlDe(_))));
+        assert_eq!(
+            err.to_string(),
+            "parse: There was an error deserializing TOML: expected a table key, found an equals at line 1 column 1"
+        );
+    }
+
+    #[test]
+    fn eq() {
+        assert_eq!(
+            Error::invalid_default_environment(),
+            Error::from("env: invalid default environment")
+        );
+        assert_ne!(
+            Error::invalid_default_environment(),
+            Error::from("parse: invalid default environment")
+        );
+        assert_eq!(
+            Error::var("TOMLENV", VarError::NotPresent),
+            Error::from("env: unable to read environment variable 'TOMLENV'")
+        );
+    }
+
+    #[test]
+    fn exit_code() {
+        let err = Error::invalid_runtime_environment("blah");
+        assert_eq!(err.code_number(), 2);
+        assert_eq!(err.exit_code(), 78);
+        assert_eq!(ErrCode::Io.number(), 5);
+        assert_eq!(ErrCode::Io.exit_code(), 74);
+        assert_eq!(ErrCode::Parse.exit_code(), 65);
+    }
+
+    #[test]
+    fn backtrace() {
+        let err = Error::invalid_default_environment();
+        assert!(matches!(
+            err.backtrace().status(),
+            BacktraceStatus::Captured | BacktraceStatus::Disabled
+        ));
+    }
+
+    #[test]
+    fn caller() {
+        let line = line!() + 1;
+        let err = Error::invalid_runtime_environment("blah");
+        assert_eq!(err.caller().file(), file!());
+        assert_eq!(err.caller().line(), line);
+    }
+
+    #[test]
+    fn var() {
+        let missing = Error::var("TOMLENV", VarError::NotPresent);
+        assert_eq!(
+            missing.to_string(),
+            "env: unable to read environment variable 'TOMLENV': environment variable not found"
+        );
+        assert!(missing.source().is_some());
+
+        let not_unicode = Error::var("TOMLENV", VarError::NotUnicode(OsString::from("prod")));
+        assert_eq!(
+            not_unicode.to_string(),
+            "env: unable to read environment variable 'TOMLENV': environment variable was not valid unicode: \"prod\""
+        );
+        assert!(not_unicode.source().is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "json")]
+    fn json() {
+        let err = Error::from(serde_json::from_str::<u8>("x").expect_err("x is not a u8"));
+        assert!(err.to_string().starts_with("parse: "));
+        assert!(err.source().is_some());
+    }
+}
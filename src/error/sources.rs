@@ -8,12 +8,13 @@
 
 //! Error Sources
 use crate::error::{ErrCode, Error};
-use std::fmt;
 
 macro_rules! dep_error {
-    ($error:ty, $kind:expr, $code:expr, $reason:expr) => {
+    ($(#[$attr:meta])* $error:ty, $kind:expr, $code:expr, $reason:expr) => {
+        $(#[$attr])*
         impl From<$error> for Error {
             #[must_use]
+            #[track_caller]
             fn from(inner: $error) -> Self {
                 Self::new($code, $reason, Some($kind(inner)))
             }
@@ -45,30 +46,71 @@ dep_error!(
     ErrCode::Parse,
     "There was an error serializing TOML"
 );
+dep_error!(
+    std::str::Utf8Error,
+    ErrSource::Utf8,
+    ErrCode::Parse,
+    "There was an error decoding UTF-8"
+);
+dep_error!(
+    #[cfg(feature = "json")]
+    serde_json::Error,
+    ErrSource::Json,
+    ErrCode::Parse,
+    "There was an error processing JSON"
+);
+dep_error!(
+    #[cfg(feature = "yaml")]
+    serde_yaml::Error,
+    ErrSource::Yaml,
+    ErrCode::Parse,
+    "There was an error processing YAML"
+);
+dep_error!(
+    #[cfg(feature = "notify")]
+    notify::Error,
+    ErrSource::Notify,
+    ErrCode::Io,
+    "There was an error watching for changes"
+);
 
-/// Error Source
-#[derive(Debug)]
+/// The underlying cause of an `Error`
+#[derive(Debug, thiserror::Error)]
 #[allow(clippy::large_enum_variant, variant_size_differences)]
-pub(crate) enum ErrSource {
+#[non_exhaustive]
+pub enum ErrSource {
+    /// An error converting a value into an environment
+    #[error("{0}")]
+    Environment(Box<dyn std::error::Error + Send + Sync>),
     /// An I/O error
+    #[error("{0}")]
     Io(std::io::Error),
+    /// An error deserializing or serializing JSON
+    #[cfg(feature = "json")]
+    #[error("{0}")]
+    Json(serde_json::Error),
+    /// An error watching for changes
+    #[cfg(feature = "notify")]
+    #[error("{0}")]
+    Notify(notify::Error),
     /// An error deserializing TOML
+    #[error("{0}")]
     TomlDe(toml::de::Error),
     /// An error serializing TOML
+    #[error("{0}")]
     TomlSer(toml::ser::Error),
+    /// An error decoding UTF-8
+    #[error("{0}")]
+    Utf8(std::str::Utf8Error),
+    /// An error fetching a URL
+    #[cfg(feature = "http")]
+    #[error("{0}")]
+    Http(Box<ureq::Error>),
     /// An error reading an environment variable
+    #[error("{0}")]
     Var(std::env::VarError),
-}
-
-impl std::error::Error for ErrSource {}
-
-impl fmt::Display for ErrSource {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Io(source) => write!(f, "{source}"),
-            Self::TomlDe(source) => write!(f, "{source}"),
-            Self::TomlSer(source) => write!(f, "{source}"),
-            Self::Var(source) => write!(f, "{source}"),
-        }
-    }
+    /// An error deserializing or serializing YAML
+    #[cfg(feature = "yaml")]
+    #[error("{0}")]
+    Yaml(serde_yaml::Error),
 }
@@ -0,0 +1,190 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! `tomlenv` custom hierarchy trait and macro
+use serde::de::DeserializeOwned;
+use std::convert::TryFrom;
+#[cfg(feature = "indexmap")]
+use std::hash::Hash;
+
+/// The traits required of any `Environments` key: `Ord`, or `Hash` and `Eq`
+/// with the `indexmap` feature.
+///
+/// This is implemented for every type with the required traits.
+#[cfg(not(feature = "indexmap"))]
+pub trait Key: Ord {}
+
+#[cfg(not(feature = "indexmap"))]
+impl<S> Key for S where S: Ord {}
+
+/// The traits required of any `Environments` key: `Ord`, or `Hash` and `Eq`
+/// with the `indexmap` feature.
+///
+/// This is implemented for every type with the required traits.
+#[cfg(feature = "indexmap")]
+pub trait Key: Hash + Eq {}
+
+#[cfg(feature = "indexmap")]
+impl<S> Key for S where S: Hash + Eq {}
+
+/// The traits required of an environment hierarchy used as the key of
+/// `Environments`.
+///
+/// This is implemented for every type with the required traits, so there
+/// is nothing to implement directly.  `Environment` and `StringEnv` are
+/// provided, and `#[derive(Hierarchy)]` or `hierarchy!` can generate the
+/// impls for a custom enum.  `Serialize` is also needed to write the
+/// environments back out.
+pub trait Hierarchy: DeserializeOwned + Key + TryFrom<String> {}
+
+impl<S> Hierarchy for S where S: DeserializeOwned + Key + TryFrom<String> {}
+
+/// A hierarchy whose environments can all be listed, e.g. for
+/// `Environments::example`.
+///
+/// This is implemented for `Environment`, and by `#[derive(Hierarchy)]` and
+/// `hierarchy!`.
+pub trait Enumerable: Sized {
+    /// Every environment, in hierarchy order.
+    fn all() -> Vec<Self>;
+}
+
+/// Define a custom environment hierarchy enum, along with its `Display`,
+/// `TryFrom<&str>`, `TryFrom<String>`, `Serialize`, `Deserialize`, and
+/// `Enumerable` impls.
+///
+/// Each variant is mapped to the given string.  The hierarchy is ordered by
+/// declaration, so declare the most restrictive environment first.
+///
+/// # Example
+///
+/// ```
+/// # use tomlenv::{hierarchy, Environments, Result};
+/// # use serde::{Deserialize, Serialize};
+/// #
+/// # fn foo() -> Result<()> {
+/// hierarchy! {
+///     /// My environment hierarchy
+///     pub MyHierarchy {
+///         Prod => "prod",
+///         Cert => "ce",
+///         Local => "local",
+///     }
+/// }
+///
+/// #[derive(Debug, Deserialize, Serialize)]
+/// struct RuntimeEnv {
+///     name: String,
+/// }
+///
+/// let envs: Environments<MyHierarchy, RuntimeEnv> =
+///     "[envs.ce]\nname = \"Certification\"\n".parse()?;
+/// assert_eq!(envs.current_from_value("ce")?.name, "Certification");
+/// assert_eq!(MyHierarchy::Cert.to_string(), "ce");
+/// assert!(MyHierarchy::Prod < MyHierarchy::Local);
+/// #   Ok(())
+/// # }
+/// # foo().unwrap();
+/// ```
+#[macro_export]
+macro_rules! hierarchy {
+    (
+        $(#[$meta:meta])*
+        $vis:vis $name:ident {
+            $($(#[$variant_meta:meta])* $variant:ident => $token:literal),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        $vis enum $name {
+            $($(#[$variant_meta])* $variant,)+
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(match self {
+                    $(Self::$variant => $token,)+
+                })
+            }
+        }
+
+        impl $crate::Enumerable for $name {
+            fn all() -> ::std::vec::Vec<Self> {
+                ::std::vec![$(Self::$variant,)+]
+            }
+        }
+
+        impl ::std::convert::TryFrom<&str> for $name {
+            type Error = $crate::Error;
+
+            fn try_from(env: &str) -> $crate::Result<Self> {
+                match env {
+                    $($token => Ok(Self::$variant),)+
+                    _ => Err($crate::Error::invalid_runtime_environment(env)),
+                }
+            }
+        }
+
+        impl ::std::convert::TryFrom<::std::string::String> for $name {
+            type Error = $crate::Error;
+
+            fn try_from(env: ::std::string::String) -> $crate::Result<Self> {
+                <Self as ::std::convert::TryFrom<&str>>::try_from(&env[..])
+            }
+        }
+
+        impl $crate::__private::serde::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: $crate::__private::serde::Serializer,
+            {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> $crate::__private::serde::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: $crate::__private::serde::Deserializer<'de>,
+            {
+                let env = <::std::string::String as $crate::__private::serde::Deserialize>::deserialize(
+                    deserializer,
+                )?;
+                <Self as ::std::convert::TryFrom<&str>>::try_from(&env[..]).map_err(|_e| {
+                    <D::Error as $crate::__private::serde::de::Error>::custom(format!(
+                        "invalid environment '{}'",
+                        env
+                    ))
+                })
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Enumerable;
+    use std::convert::TryFrom;
+
+    hierarchy! {
+        Tiers {
+            Prod => "prod",
+            Cert => "ce",
+            Local => "local",
+        }
+    }
+
+    #[test]
+    fn hierarchy() {
+        assert!(Tiers::Prod < Tiers::Cert && Tiers::Cert < Tiers::Local);
+        assert_eq!(Tiers::Cert.to_string(), "ce");
+        assert_eq!(Tiers::try_from("local").ok(), Some(Tiers::Local));
+        assert!(Tiers::try_from(String::from("cert")).is_err());
+        assert_eq!(Tiers::all(), vec![Tiers::Prod, Tiers::Cert, Tiers::Local]);
+    }
+}
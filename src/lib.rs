@@ -17,8 +17,9 @@
 //! On the key side, you can use the `Environment` hierarchy defined by this
 //! library (Prod -> Stage -> Test -> Dev -> Local), or you can define your own
 //! custom hierarchy to use with the `Environments` struct.  If your define a
-//! custom hierarchy you must implement the `Deserialize`, `Serialize`, `Ord`,
-//! `PartialOrd`, and `TryFrom<String>` traits.  See more in the
+//! custom hierarchy you must implement the `Deserialize`, `Ord`, `PartialOrd`,
+//! and `TryFrom<String>` traits, which together make up the `Hierarchy`
+//! trait, and `Serialize` to write the environments back out.  See more in the
 //! [Custom Environment Hierarchy](#custom-environment-hierarchy) section below.
 //!
 //! # Usage
@@ -94,24 +95,52 @@
 //! # }
 //! ```
 //!
+//! # Features
+//! * `clap`: Load `Environments` from clap `ArgMatches` (off by default).
+//! * `json`: Load and save `Environments` as JSON (off by default).
+//! * `yaml`: Load and save `Environments` as YAML (off by default).
+//! * `tokio`: Load `Environments` from a path asynchronously (off by default).
+//! * `notify`: Reload `Environments` when the file changes (off by default).
+//! * `derive`: Derive a custom hierarchy with `#[derive(Hierarchy)]` (off by default).
+//! * `gzip`: Load `Environments` from a gzip-compressed file (off by default).
+//! * `http`: Load `Environments` from an HTTP(S) URL (off by default).
+//! * `indexmap`: Keep environments in the order they were declared, rather than
+//! in hierarchy order (off by default).
+//! * `tracing`: Emit `tracing` events when reading files and selecting the
+//! current environment (off by default).
+//!
 //! # Custom Environment Hierarchy
 //! If you wish to forego using the `Environment` hierarchy supplied by this
 //! library, implement a custom hierarchy instead.  There are a few traits you
-//! must implement in order to work with `Environments`.
+//! must implement in order to work with `Environments`.  The `Hierarchy`
+//! trait bundles them, and is implemented for any type that has them all.
 //!
 //! ## Required
-//! * `Deserialize` and `Serialize`: These are required to translate
-//! to/from TOML.
+//! * `Deserialize`: This is required to translate from TOML.  `Serialize` is
+//! also required to translate back to TOML.
 //! * `Ord` and `PartialOrd`:  These are required to maintain proper ordering for
-//! your hierarchy and ensure serialized TOML is always in the same order.
+//! your hierarchy and ensure serialized TOML is always in the same order.  With
+//! the `indexmap` feature, `Hash` and `Eq` are required instead.
 //! * `TryFrom<String>`: This is used to translate the environment variable `env`
-//! into your hierarchy type.
+//! into your hierarchy type.  The conversion error is kept as the source of
+//! the returned `Error`, so it must convert into a boxed `std::error::Error`.
 //!
 //! ## Optional
 //! * `Display`: Used to convert your hierarchy type to a formatted string.
 //! Useful if you need to show what environment you are using.
 //! * `TryFrom<&'a str>`: In this case, used by the custom deserializer.
 //!
+//! With the `derive` feature enabled, `#[derive(Hierarchy)]` generates the
+//! `Display`, `TryFrom`, `Serialize`, and `Deserialize` impls for an enum of
+//! unit variants.  Each variant is represented by its lowercase name, or by
+//! the string given with `#[env("...")]`.
+//!
+//! The `hierarchy!` macro generates the same impls along with the enum
+//! itself, mapping each variant to a string in declaration order.
+//!
+//! For environment names that aren't known at compile time, use the
+//! provided `StringEnv` hierarchy, which accepts any string.
+//!
 //! Below is an example of a custom hierarchy.  This example has a custom
 //! serializer/deserializer, but that shouldn't be necessary in all cases.
 //!
@@ -435,7 +464,34 @@
 
 mod env;
 mod error;
+mod hierarchy;
+
+#[cfg(all(test, not(feature = "clap")))]
+use dirs as _;
+#[cfg(all(test, not(feature = "tokio")))]
+use tokio as _;
+#[cfg(all(test, feature = "derive"))]
+extern crate self as tomlenv;
 
+pub use env::Base64;
+pub use env::EnvDiff;
 pub use env::Environment;
 pub use env::Environments;
-pub use error::{Error, Result};
+pub use env::EnvironmentsBuilder;
+#[cfg(feature = "notify")]
+pub use env::EnvironmentsWatcher;
+pub use env::Merge;
+pub use env::Secret;
+pub use env::SharedEnvironments;
+pub use env::StdVarReader;
+pub use env::StringEnv;
+pub use env::VarReader;
+pub use error::{ErrCode, ErrSource, Error, ErrorKind, Result};
+pub use hierarchy::{Enumerable, Hierarchy, Key};
+#[cfg(feature = "derive")]
+pub use tomlenv_derive::Hierarchy;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+}
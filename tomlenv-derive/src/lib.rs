@@ -0,0 +1,174 @@
+// Copyright (c) 2018 tomlenv developers
+//
+// Licensed under the Apache License, Version 2.0
+// <LICENSE-APACHE or http://www.apache.org/licenses/LICENSE-2.0> or the MIT
+// license <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. All files in the project carrying such notice may not be copied,
+// modified, or distributed except according to those terms.
+
+//! Derive macros for `tomlenv`.  Use these through the `derive` feature of
+//! `tomlenv` rather than depending on this crate directly.
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derive `Display`, `TryFrom<&str>`, `TryFrom<String>`, `Serialize`,
+/// `Deserialize`, and `Enumerable` for a custom environment hierarchy.
+///
+/// The hierarchy must be an enum of unit variants.  Each variant is
+/// represented by its name in lowercase, or by the string given with
+/// `#[env("...")]`.
+///
+/// ```
+/// # use tomlenv::Hierarchy;
+/// #[derive(Clone, Copy, Debug, Eq, Hash, Hierarchy, Ord, PartialEq, PartialOrd)]
+/// enum MyHierarchy {
+///     Prod,
+///     #[env("ce")]
+///     Cert,
+///     #[env("sb")]
+///     Sandbox,
+///     Local,
+/// }
+/// ```
+///
+/// Each string must name only one variant.
+///
+/// ```compile_fail
+/// # use tomlenv::Hierarchy;
+/// #[derive(Clone, Copy, Debug, Eq, Hash, Hierarchy, Ord, PartialEq, PartialOrd)]
+/// enum MyHierarchy {
+///     Prod,
+///     #[env("prod")]
+///     Production,
+/// }
+/// ```
+#[proc_macro_derive(Hierarchy, attributes(env))]
+pub fn derive_hierarchy(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            input,
+            "Hierarchy can only be derived for enums",
+        ));
+    };
+
+    let mut variants = Vec::new();
+    let mut tokens = Vec::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "Hierarchy variants cannot have fields",
+            ));
+        }
+        let mut token = variant.ident.to_string().to_lowercase();
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("env"))
+        {
+            token = attr.parse_args::<LitStr>()?.value();
+        }
+        if let Some(index) = tokens.iter().position(|other| *other == token) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                format!(
+                    "environment name \"{token}\" is already used by {}",
+                    variants[index]
+                ),
+            ));
+        }
+        variants.push(&variant.ident);
+        tokens.push(token);
+    }
+
+    let name = &input.ident;
+    let visitor = quote::format_ident!("{}Visitor", name);
+    let expecting = format!("one of {}", tokens.join(", "));
+
+    Ok(quote! {
+        impl ::std::fmt::Display for #name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(match self {
+                    #(Self::#variants => #tokens,)*
+                })
+            }
+        }
+
+        impl ::tomlenv::Enumerable for #name {
+            fn all() -> ::std::vec::Vec<Self> {
+                ::std::vec![#(Self::#variants,)*]
+            }
+        }
+
+        impl ::std::convert::TryFrom<&str> for #name {
+            type Error = ::tomlenv::Error;
+
+            fn try_from(env: &str) -> ::tomlenv::Result<Self> {
+                match env {
+                    #(#tokens => Ok(Self::#variants),)*
+                    _ => Err(::tomlenv::Error::invalid_runtime_environment(env)),
+                }
+            }
+        }
+
+        impl ::std::convert::TryFrom<::std::string::String> for #name {
+            type Error = ::tomlenv::Error;
+
+            fn try_from(env: ::std::string::String) -> ::tomlenv::Result<Self> {
+                <Self as ::std::convert::TryFrom<&str>>::try_from(&env[..])
+            }
+        }
+
+        impl ::tomlenv::__private::serde::Serialize for #name {
+            fn serialize<S>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error>
+            where
+                S: ::tomlenv::__private::serde::Serializer,
+            {
+                serializer.serialize_str(match self {
+                    #(Self::#variants => #tokens,)*
+                })
+            }
+        }
+
+        impl<'de> ::tomlenv::__private::serde::Deserialize<'de> for #name {
+            fn deserialize<D>(deserializer: D) -> ::std::result::Result<Self, D::Error>
+            where
+                D: ::tomlenv::__private::serde::Deserializer<'de>,
+            {
+                struct #visitor;
+
+                impl<'de> ::tomlenv::__private::serde::de::Visitor<'de> for #visitor {
+                    type Value = #name;
+
+                    fn expecting(
+                        &self,
+                        formatter: &mut ::std::fmt::Formatter<'_>,
+                    ) -> ::std::fmt::Result {
+                        formatter.write_str(#expecting)
+                    }
+
+                    fn visit_str<E>(self, value: &str) -> ::std::result::Result<#name, E>
+                    where
+                        E: ::tomlenv::__private::serde::de::Error,
+                    {
+                        <#name as ::std::convert::TryFrom<&str>>::try_from(value)
+                            .map_err(|_e| E::custom(format!("invalid environment '{}'", value)))
+                    }
+                }
+
+                deserializer.deserialize_string(#visitor)
+            }
+        }
+    })
+}